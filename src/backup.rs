@@ -0,0 +1,259 @@
+use crate::create_symlink;
+use eros::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Directory, relative to the destination, where backups of overwritten files are kept
+const BACKUPS_DIR: &str = ".stamp/backups";
+const MANIFEST_FILE: &str = "manifest.json";
+const FILES_DIR: &str = "files";
+
+/// Record of a single render into a destination, used by `stamp undo`
+#[derive(Debug, Deserialize, Serialize)]
+struct Manifest {
+    template: PathBuf,
+    /// Files that did not exist before the render
+    created: Vec<ManifestEntry>,
+    /// Files that existed before the render. The originals are stored in the backup's `files` directory
+    overwritten: Vec<ManifestEntry>,
+    /// Empty directories that did not exist before the render, relative to the destination
+    #[serde(default)]
+    created_dirs: Vec<PathBuf>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ManifestEntry {
+    /// Path relative to the destination
+    path: PathBuf,
+    /// State of the file right after the render, used to detect later modifications
+    len: u64,
+    modified: Option<SystemTime>,
+    /// Target of an overwritten symlink, which is recreated rather than restored from the `files` directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link_target: Option<PathBuf>,
+}
+
+/// An in progress backup of the files a render overwrites
+pub struct Backup {
+    destination: PathBuf,
+    dir: PathBuf,
+    manifest: Manifest,
+}
+
+impl Backup {
+    pub fn start(destination: &Path, template: &Path) -> eros::Result<Self> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let dir = destination.join(BACKUPS_DIR).join(timestamp.to_string());
+        Ok(Backup {
+            destination: destination.to_path_buf(),
+            dir,
+            manifest: Manifest {
+                template: template.to_path_buf(),
+                created: Vec::new(),
+                overwritten: Vec::new(),
+                created_dirs: Vec::new(),
+            },
+        })
+    }
+
+    /// Saves a copy of `path` if it exists. Symlinks, even dangling ones, are saved as their target. Must be called
+    /// before `path` is written to.
+    pub fn save_original(&mut self, path: &Path) -> eros::Result<()> {
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return Ok(());
+        };
+        let relative = self.relative(path)?;
+        if metadata.file_type().is_symlink() {
+            self.manifest.overwritten.push(ManifestEntry {
+                path: relative,
+                len: 0,
+                modified: None,
+                link_target: Some(fs::read_link(path)?),
+            });
+            return Ok(());
+        }
+        let backup_path = self.dir.join(FILES_DIR).join(&relative);
+        if let Some(parent) = backup_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(path, &backup_path)
+            .with_context(|| format!("Could not back up `{}`", path.to_string_lossy()))?;
+        self.manifest.overwritten.push(ManifestEntry {
+            path: relative,
+            len: 0,
+            modified: None,
+            link_target: None,
+        });
+        Ok(())
+    }
+
+    /// Records `path` as written by the render
+    pub fn record_written(&mut self, path: &Path) -> eros::Result<()> {
+        let relative = self.relative(path)?;
+        let metadata = fs::symlink_metadata(path)?;
+        let len = metadata.len();
+        let modified = metadata.modified().ok();
+        if let Some(entry) = self
+            .manifest
+            .overwritten
+            .iter_mut()
+            .find(|e| e.path == relative)
+        {
+            entry.len = len;
+            entry.modified = modified;
+        } else {
+            self.manifest.created.push(ManifestEntry {
+                path: relative,
+                len,
+                modified,
+                link_target: None,
+            });
+        }
+        Ok(())
+    }
+
+    /// Records the empty directory `path` as created by the render
+    pub fn record_created_dir(&mut self, path: &Path) -> eros::Result<()> {
+        let relative = self.relative(path)?;
+        self.manifest.created_dirs.push(relative);
+        Ok(())
+    }
+
+    /// Writes the manifest. Returns the backup directory if anything was overwritten.
+    pub fn finish(self) -> eros::Result<Option<PathBuf>> {
+        fs::create_dir_all(&self.dir)?;
+        let contents = serde_json::to_string_pretty(&self.manifest)?;
+        fs::write(self.dir.join(MANIFEST_FILE), contents)?;
+        if self.manifest.overwritten.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(self.dir))
+        }
+    }
+
+    fn relative(&self, path: &Path) -> eros::Result<PathBuf> {
+        let relative = path.strip_prefix(&self.destination).with_context(|| {
+            format!("`{}` is not inside the destination", path.to_string_lossy())
+        })?;
+        Ok(relative.to_path_buf())
+    }
+}
+
+/// Reverts the last render into `destination` that was done with `--overwrite-conflicts`
+pub fn undo(destination: PathBuf) -> eros::Result<()> {
+    let backups_dir = destination.join(BACKUPS_DIR);
+    let Some(backup_dir) = latest_backup(&backups_dir)? else {
+        bail!("No render to undo in `{}`", destination.to_string_lossy());
+    };
+
+    let manifest_path = backup_dir.join(MANIFEST_FILE);
+    let contents = fs::read_to_string(&manifest_path)
+        .with_context(|| format!("could not read `{}`", manifest_path.to_string_lossy()))?;
+    let manifest: Manifest = serde_json::from_str(&contents).with_context(|| {
+        format!(
+            "Backup manifest from `{}` is not valid",
+            manifest_path.to_string_lossy()
+        )
+    })?;
+
+    let mut modified = Vec::new();
+    for entry in manifest.created.iter().chain(&manifest.overwritten) {
+        let path = destination.join(&entry.path);
+        if is_modified(&path, entry) {
+            modified.push(path);
+        }
+    }
+    if !modified.is_empty() {
+        eprintln!("Files modified since the render:");
+        for path in modified {
+            eprintln!(" - {}", path.to_string_lossy());
+        }
+        bail!("Refusing to undo the render since it would lose these changes.");
+    }
+
+    for entry in &manifest.created {
+        let path = destination.join(&entry.path);
+        if path.symlink_metadata().is_ok() {
+            fs::remove_file(&path)?;
+            remove_empty_parents(&path, &destination);
+        }
+    }
+    for entry in &manifest.overwritten {
+        let original = backup_dir.join(FILES_DIR).join(&entry.path);
+        let path = destination.join(&entry.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Removed first so a symlink written by the render is replaced rather than written through
+        if path.symlink_metadata().is_ok() {
+            fs::remove_file(&path)?;
+        }
+        let restored = match &entry.link_target {
+            Some(target) => {
+                let target_is_dir = path
+                    .parent()
+                    .is_some_and(|parent| parent.join(target).is_dir());
+                create_symlink(target, &path, target_is_dir)
+            }
+            None => fs::copy(&original, &path).map(|_| ()),
+        };
+        restored.with_context(|| format!("Could not restore `{}`", path.to_string_lossy()))?;
+    }
+    // Directories that have been filled since the render are kept
+    for dir in &manifest.created_dirs {
+        let path = destination.join(dir);
+        if fs::remove_dir(&path).is_ok() {
+            remove_empty_parents(&path, &destination);
+        }
+    }
+
+    fs::remove_dir_all(&backup_dir)?;
+    remove_empty_parents(&backup_dir, &destination);
+
+    println!(
+        "Render of `{}` undone in {:?}",
+        manifest.template.to_string_lossy(),
+        destination
+    );
+    Ok(())
+}
+
+fn latest_backup(backups_dir: &Path) -> eros::Result<Option<PathBuf>> {
+    if !backups_dir.is_dir() {
+        return Ok(None);
+    }
+    let mut latest: Option<(u128, PathBuf)> = None;
+    for entry in fs::read_dir(backups_dir)? {
+        let entry = entry?;
+        let Ok(timestamp) = entry.file_name().to_string_lossy().parse::<u128>() else {
+            continue;
+        };
+        if latest.as_ref().is_none_or(|(t, _)| timestamp > *t) {
+            latest = Some((timestamp, entry.path()));
+        }
+    }
+    Ok(latest.map(|(_, path)| path))
+}
+
+/// A missing file counts as unmodified since there is nothing to lose
+fn is_modified(path: &Path, entry: &ManifestEntry) -> bool {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata.len() != entry.len || metadata.modified().ok() != entry.modified,
+        Err(_) => false,
+    }
+}
+
+/// Removes the now empty directories between `path` and `root`
+fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut current = path.parent();
+    while let Some(dir) = current {
+        if dir == root || !dir.starts_with(root) || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        current = dir.parent();
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{
+    discovery::FoundTemplate,
+    registry::{config_dir, load_registry},
+    resolve_template,
+};
+use eros::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const CONFIG_FILE: &str = "config.toml";
+
+/// Question ids answered by default with a `git config` value, when the user config has no default for them
+const GIT_DEFAULTS: &[(&str, &str)] = &[
+    ("author", "user.name"),
+    ("author_name", "user.name"),
+    ("author_email", "user.email"),
+    ("email", "user.email"),
+];
+
+type Answers = BTreeMap<String, toml::Value>;
+
+/// The user config, kept next to the user registry
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct UserConfig {
+    /// Default answers for every template, keyed by question id
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    defaults: Answers,
+    /// Default answers for the templates of a source, keyed by source name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    sources: BTreeMap<String, Answers>,
+    /// Default answers for a single template, keyed by qualified name, e.g. `work/axum_server`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    templates: BTreeMap<String, Answers>,
+}
+
+impl UserConfig {
+    fn answers(&self, scope: &Scope) -> Option<&Answers> {
+        match scope {
+            Scope::Global => Some(&self.defaults),
+            Scope::Source(name) => self.sources.get(name),
+            Scope::Template(name) => self.templates.get(name),
+        }
+    }
+
+    fn answers_mut(&mut self, scope: &Scope) -> &mut Answers {
+        match scope {
+            Scope::Global => &mut self.defaults,
+            Scope::Source(name) => self.sources.entry(name.clone()).or_default(),
+            Scope::Template(name) => self.templates.entry(name.clone()).or_default(),
+        }
+    }
+}
+
+/// Which templates a default answer applies to
+pub enum Scope {
+    Global,
+    Source(String),
+    Template(String),
+}
+
+impl Scope {
+    /// Resolves `--template` and `--source` arguments. Templates are addressed with the same names as `use` and stored
+    /// by qualified name. Names already in the config are accepted as is, so defaults of removed templates and
+    /// sources can still be unset.
+    pub fn resolve(
+        template: Option<&str>,
+        source: Option<&str>,
+        registry_override: Option<&Path>,
+    ) -> eros::Result<Self> {
+        let config = read_config()?;
+        match (template, source) {
+            (Some(template), _) if config.templates.contains_key(template) => {
+                Ok(Scope::Template(template.to_string()))
+            }
+            (Some(template), _) => Ok(Scope::Template(
+                resolve_template(Some(template), registry_override)?.qualified_name(),
+            )),
+            (None, Some(source)) => {
+                let registered = load_registry(registry_override)?
+                    .sources
+                    .iter()
+                    .any(|s| s.namespace() == source);
+                if !registered && !config.sources.contains_key(source) {
+                    bail!("Source `{}` not found in registry", source);
+                }
+                Ok(Scope::Source(source.to_string()))
+            }
+            (None, None) => Ok(Scope::Global),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Scope::Global => "every template".to_string(),
+            Scope::Source(name) => format!("source `{}`", name),
+            Scope::Template(name) => format!("template `{}`", name),
+        }
+    }
+}
+
+/// The default answers for `template`, or for a template outside the registry when `None`. Template defaults take
+/// precedence over source defaults, which take precedence over global defaults. Defaults from `git config` are looked
+/// up separately with [`git_default`].
+pub fn default_answers(
+    template: Option<&FoundTemplate>,
+) -> eros::Result<HashMap<String, toml::Value>> {
+    let mut config = read_config()?;
+    let mut defaults: HashMap<String, toml::Value> = config.defaults.into_iter().collect();
+    if let Some(template) = template {
+        if let Some(answers) = config.sources.remove(&template.source.namespace()) {
+            defaults.extend(answers);
+        }
+        if let Some(answers) = config.templates.remove(&template.qualified_name()) {
+            defaults.extend(answers);
+        }
+    }
+    Ok(defaults)
+}
+
+pub fn set_default(id: String, value: String, scope: Scope) -> eros::Result<()> {
+    let mut config = read_config()?;
+    config
+        .answers_mut(&scope)
+        .insert(id.clone(), toml::Value::String(value));
+    save_config(&config)?;
+    println!("Default answer for `{}` set for {}", id, scope.describe());
+    Ok(())
+}
+
+pub fn get_default(id: &str, scope: Scope) -> eros::Result<()> {
+    let config = read_config()?;
+    match config.answers(&scope).and_then(|answers| answers.get(id)) {
+        Some(value) => println!("{}", display_value(value)),
+        None => bail!(
+            "No default answer for `{}` set for {}",
+            id,
+            scope.describe()
+        ),
+    }
+    Ok(())
+}
+
+pub fn unset_default(id: &str, scope: Scope) -> eros::Result<()> {
+    let mut config = read_config()?;
+    if config.answers_mut(&scope).remove(id).is_none() {
+        bail!(
+            "No default answer for `{}` set for {}",
+            id,
+            scope.describe()
+        );
+    }
+    config.sources.retain(|_, answers| !answers.is_empty());
+    config.templates.retain(|_, answers| !answers.is_empty());
+    save_config(&config)?;
+    println!("Default answer for `{}` unset for {}", id, scope.describe());
+    Ok(())
+}
+
+pub fn list_defaults() -> eros::Result<()> {
+    let config = read_config()?;
+    let git_defaults: Vec<(String, toml::Value)> = git_defaults()
+        .into_iter()
+        .filter(|(id, _)| !config.defaults.contains_key(id))
+        .collect();
+    if config.defaults.is_empty()
+        && config.sources.is_empty()
+        && config.templates.is_empty()
+        && git_defaults.is_empty()
+    {
+        println!("No default answers set. Add one with `stamp config set <id> <value>`.");
+        return Ok(());
+    }
+
+    let print_answers = |heading: String, answers: &Answers| {
+        println!("{}", console::style(heading).bold());
+        for (id, value) in answers {
+            println!("  {} = {}", id, display_value(value));
+        }
+    };
+    if !config.defaults.is_empty() || !git_defaults.is_empty() {
+        println!("{}", console::style("Every template").bold());
+        for (id, value) in &config.defaults {
+            println!("  {} = {}", id, display_value(value));
+        }
+        for (id, value) in &git_defaults {
+            println!(
+                "  {} = {}  {}",
+                id,
+                display_value(value),
+                console::style("(from git config)").dim()
+            );
+        }
+    }
+    for (name, answers) in &config.sources {
+        print_answers(format!("Source `{}`", name), answers);
+    }
+    for (name, answers) in &config.templates {
+        print_answers(format!("Template `{}`", name), answers);
+    }
+    Ok(())
+}
+
+/// Strings are shown without quotes
+fn display_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// The default from `git config` for `id`, if it is in [`GIT_DEFAULTS`]. Only used when the user config has no default.
+pub fn git_default(id: &str) -> Option<toml::Value> {
+    let (_, key) = GIT_DEFAULTS
+        .iter()
+        .find(|(default_id, _)| *default_id == id)?;
+    git_config(key).map(toml::Value::String)
+}
+
+/// Defaults from `git config` for the ids in [`GIT_DEFAULTS`]
+fn git_defaults() -> Vec<(String, toml::Value)> {
+    let mut values: HashMap<&str, Option<String>> = HashMap::new();
+    let mut defaults = Vec::new();
+    for (id, key) in GIT_DEFAULTS {
+        let value = values.entry(key).or_insert_with(|| git_config(key));
+        if let Some(value) = value {
+            defaults.push((id.to_string(), toml::Value::String(value.clone())));
+        }
+    }
+    defaults
+}
+
+fn git_config(key: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["config", "--get", key])
+        .output()
+        .ok()?;
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !value.is_empty()).then_some(value)
+}
+
+fn read_config() -> eros::Result<UserConfig> {
+    let config_path = get_config_path()?;
+    if !config_path.exists() {
+        return Ok(UserConfig::default());
+    }
+    let contents = fs::read_to_string(&config_path)?;
+    let config = toml::from_str(&contents)
+        .with_context(|| format!("`{}` is not valid", config_path.to_string_lossy()))?;
+    Ok(config)
+}
+
+fn save_config(config: &UserConfig) -> eros::Result<()> {
+    fs::write(get_config_path()?, toml::to_string_pretty(config)?)?;
+    Ok(())
+}
+
+fn get_config_path() -> eros::Result<PathBuf> {
+    Ok(config_dir()?.join(CONFIG_FILE))
+}
@@ -0,0 +1,322 @@
+use crate::{
+    build_glob_set,
+    registry::{Source, SourceOptions},
+    TemplateConfig,
+};
+use directories::ProjectDirs;
+use globset::GlobSet;
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+const INDEX_FILE: &str = "template_index.json";
+/// Bumped whenever what the index stores changes, so indexes written by older versions are rebuilt
+const INDEX_VERSION: u32 = 1;
+/// How deep sources are searched for templates unless configured otherwise
+const DEFAULT_MAX_DEPTH: usize = 4;
+
+#[derive(Clone)]
+pub struct FoundTemplate {
+    pub path: PathBuf,
+    pub name: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub source: Source,
+}
+
+impl FoundTemplate {
+    /// The fully qualified name, e.g. `work/axum_server`
+    pub fn qualified_name(&self) -> String {
+        let relative = self
+            .path
+            .strip_prefix(&self.source.path)
+            .unwrap_or(&self.path);
+        format!("{}/{}", self.source.namespace(), relative.to_string_lossy())
+    }
+}
+
+/// The templates found in the registered sources, along with any problems encountered finding them
+pub struct Discovered {
+    pub templates: Vec<FoundTemplate>,
+    /// Directories that could not be read and templates with an invalid `stamp.toml`
+    pub warnings: Vec<String>,
+}
+
+/// Templates found in each source on a previous run, so sources that have not changed need not be walked again
+#[derive(Debug, Deserialize, Serialize)]
+struct TemplateIndex {
+    #[serde(default)]
+    version: u32,
+    sources: HashMap<PathBuf, SourceIndex>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct SourceIndex {
+    /// The options the source was walked with
+    #[serde(default)]
+    options: SourceOptions,
+    /// Directories that could not be walked. Sources with any are walked again on the next run.
+    #[serde(default)]
+    walk_errors: Vec<String>,
+    /// Every directory walked. Templates can only have been added or removed if one of these changed.
+    directories: Vec<Timestamped>,
+    templates: Vec<IndexedTemplate>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Timestamped {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct IndexedTemplate {
+    path: PathBuf,
+    /// When the template's `stamp.toml` was last modified
+    config_modified: Option<SystemTime>,
+    name: String,
+    description: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Why the template's `stamp.toml` could not be read
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Finds the templates in `sources`, using the index of a previous run for sources that have not changed. With
+/// `refresh` every source is walked again.
+pub fn find_templates(sources: &[Source], refresh: bool) -> Discovered {
+    let index_path = get_index_path();
+    let index = if refresh {
+        None
+    } else {
+        index_path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| serde_json::from_str::<TemplateIndex>(&contents).ok())
+            .filter(|index| index.version == INDEX_VERSION)
+    };
+    let mut changed = index.is_none();
+    let mut index = index.unwrap_or(TemplateIndex {
+        version: INDEX_VERSION,
+        sources: HashMap::new(),
+    });
+
+    let mut templates = Vec::new();
+    let mut warnings = Vec::new();
+    for source in sources {
+        let source_index = match index.sources.remove(&source.path) {
+            Some(mut source_index) if is_fresh(&source_index, &source.options) => {
+                changed |= refresh_configs(&mut source_index);
+                source_index
+            }
+            _ => {
+                changed = true;
+                walk_source(source)
+            }
+        };
+
+        warnings.extend(source_index.walk_errors.iter().cloned());
+        for template in &source_index.templates {
+            if let Some(error) = &template.error {
+                warnings.push(error.clone());
+            }
+            templates.push(FoundTemplate {
+                path: template.path.clone(),
+                name: template.name.clone(),
+                description: template.description.clone(),
+                tags: template.tags.clone(),
+                source: source.clone(),
+            });
+        }
+        index.sources.insert(source.path.clone(), source_index);
+    }
+
+    // Sources that are no longer registered are dropped from the index
+    let registered: Vec<&PathBuf> = sources.iter().map(|s| &s.path).collect();
+    let before = index.sources.len();
+    index.sources.retain(|path, _| registered.contains(&path));
+    changed |= index.sources.len() != before;
+
+    if changed && let Some(index_path) = index_path {
+        // The index is only an optimization, so failing to save it is not an error
+        let _ = serde_json::to_string(&index).map(|contents| fs::write(index_path, contents));
+    }
+
+    Discovered {
+        templates,
+        warnings,
+    }
+}
+
+fn walk_source(source: &Source) -> SourceIndex {
+    let options = source.options.clone();
+    let mut directories = Vec::new();
+    let mut templates = Vec::new();
+    let mut walk_errors = Vec::new();
+
+    let (include, exclude) = match (
+        source_globs(&options.include, true),
+        source_globs(&options.exclude, false),
+    ) {
+        (Ok(include), Ok(exclude)) => (include, exclude),
+        (Err(err), _) | (_, Err(err)) => {
+            walk_errors.push(format!(
+                "Source `{}` has invalid options: {}",
+                source.namespace(),
+                err
+            ));
+            return SourceIndex {
+                options,
+                walk_errors,
+                directories,
+                templates,
+            };
+        }
+    };
+
+    let search_gitignored = options.search_gitignored.unwrap_or(false);
+    let root = source.path.clone();
+    let walker = WalkBuilder::new(&source.path)
+        .max_depth(Some(options.max_depth.unwrap_or(DEFAULT_MAX_DEPTH)))
+        .standard_filters(true)
+        .git_ignore(!search_gitignored)
+        .git_global(!search_gitignored)
+        .git_exclude(!search_gitignored)
+        .filter_entry(move |entry| {
+            if entry.depth() == 0 {
+                return true;
+            }
+            // Templates are not searched for templates
+            let in_template = entry
+                .path()
+                .parent()
+                .is_some_and(|parent| parent.join("stamp.toml").is_file());
+            let relative = entry.path().strip_prefix(&root).unwrap_or(entry.path());
+            !in_template && !exclude.is_match(relative)
+        })
+        .build();
+
+    for result in walker {
+        match result {
+            Ok(entry) => {
+                if entry.file_type().is_some_and(|ft| ft.is_dir()) {
+                    let path = entry.path().to_path_buf();
+                    directories.push(Timestamped {
+                        modified: modified(&path),
+                        path: path.clone(),
+                    });
+
+                    let relative = path.strip_prefix(&source.path).unwrap_or(&path);
+                    if path.join("stamp.toml").exists() && include.is_match(relative) {
+                        templates.push(read_template(path));
+                    }
+                }
+            }
+            Err(err) => {
+                walk_errors.push(format!(
+                    "Could not search source `{}`: {}",
+                    source.namespace(),
+                    err
+                ));
+            }
+        }
+    }
+
+    SourceIndex {
+        options,
+        walk_errors,
+        directories,
+        templates,
+    }
+}
+
+/// Globs are matched against paths relative to the source. Empty `include` globs match everything.
+fn source_globs(patterns: &[String], is_include: bool) -> eros::Result<GlobSet> {
+    match (patterns.is_empty(), is_include) {
+        (true, true) => build_glob_set(None),
+        (true, false) => Ok(GlobSet::empty()),
+        (false, _) => build_glob_set(Some(patterns)),
+    }
+}
+
+fn read_template(path: PathBuf) -> IndexedTemplate {
+    let config_path = path.join("stamp.toml");
+    let mut name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let mut description = None;
+    let mut tags = Vec::new();
+    let mut error = None;
+
+    let config = fs::read_to_string(&config_path)
+        .map_err(|err| err.to_string())
+        .and_then(|contents| {
+            toml::from_str::<TemplateConfig>(&contents).map_err(|err| err.message().to_string())
+        });
+    match config {
+        Ok(config) => {
+            if let Some(n) = config.meta.name {
+                name = n;
+            }
+            description = config.meta.description;
+            tags = config.meta.tags;
+        }
+        Err(err) => {
+            error = Some(format!(
+                "Could not read `{}`, so the template is listed as `{}`: {}",
+                config_path.to_string_lossy(),
+                name,
+                err
+            ));
+        }
+    }
+
+    IndexedTemplate {
+        config_modified: modified(&config_path),
+        path,
+        name,
+        description,
+        tags,
+        error,
+    }
+}
+
+fn is_fresh(source_index: &SourceIndex, options: &SourceOptions) -> bool {
+    source_index.options == *options
+        && source_index.walk_errors.is_empty()
+        && source_index
+            .directories
+            .iter()
+            .all(|directory| modified(&directory.path) == directory.modified)
+}
+
+/// Re-reads the templates whose `stamp.toml` changed. Returns whether any did.
+fn refresh_configs(source_index: &mut SourceIndex) -> bool {
+    let mut changed = false;
+    for template in &mut source_index.templates {
+        if modified(&template.path.join("stamp.toml")) != template.config_modified {
+            *template = read_template(template.path.clone());
+            changed = true;
+        }
+    }
+    changed
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn get_index_path() -> Option<PathBuf> {
+    let proj_dirs = ProjectDirs::from("com", "mcmah309", "stamp")?;
+    let cache_dir = proj_dirs.cache_dir();
+    fs::create_dir_all(cache_dir).ok()?;
+    Some(cache_dir.join(INDEX_FILE))
+}
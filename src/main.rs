@@ -1,26 +1,47 @@
-use clap::{Parser, Subcommand};
-use console::style;
-use dialoguer::{theme::ColorfulTheme, Confirm, Input, MultiSelect, Select};
-use directories::ProjectDirs;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use console::{style, Term};
+use dialoguer::{theme::ColorfulTheme, Confirm, FuzzySelect, Input, MultiSelect, Select};
+use discovery::{find_templates, Discovered, FoundTemplate};
 use eros::{bail, Context};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
 use ignore::WalkBuilder;
+use registry::{load_registry, SourceOptions};
+use renderer::{Delimiters, Renderer};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, fs, path::PathBuf, process::exit};
-use tera::Tera;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::Read,
+    path::{Component, Path, PathBuf},
+    process::exit,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+mod backup;
+mod config;
+mod discovery;
+mod registry;
+mod renderer;
+mod scaffold;
+mod watch;
 
 #[derive(Parser)]
 #[command(name = "yard", author = "Henry McMahon", version = "0.1", about =  "A cli tool for applying project templates", long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Registry file to use in addition to the discovered ones, taking precedence over them. `register` and
+    /// `remove` modify this registry instead of the user registry.
+    #[clap(long, global = true)]
+    registry: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Render a template in the registry to a destination directory
     Use {
-        /// The template name in the registry
-        name: String,
+        /// The template name in the registry. When omitted, a template is picked interactively.
+        name: Option<String>,
         /// Path to the destination folder
         #[clap(default_value = ".")]
         destination: PathBuf,
@@ -30,6 +51,21 @@ enum Commands {
         /// Skip any conflicting files
         #[clap(long, group = "conflict_strategy")]
         skip_conflicts: bool,
+        /// Format of the report of written files
+        #[clap(long, value_enum, default_value_t)]
+        format: OutputFormat,
+        /// File of answers, in toml or json, keyed by question id. Answered questions are not asked.
+        #[clap(long)]
+        answers: Option<PathBuf>,
+        /// Answer a question, e.g. `--set name=my_app`. Can be repeated.
+        #[clap(long = "set", value_parser = parse_answer)]
+        set: Vec<(String, String)>,
+        /// Print nothing after a successful render
+        #[clap(short, long, conflicts_with = "verbose")]
+        quiet: bool,
+        /// Also list unchanged files and the template file each file came from
+        #[clap(short, long)]
+        verbose: bool,
     },
     /// Render a template from a source directory to a destination directory
     From {
@@ -43,59 +79,243 @@ enum Commands {
         /// Skip any conflicting files
         #[clap(long, group = "conflict_strategy")]
         skip_conflicts: bool,
+        /// Format of the report of written files
+        #[clap(long, value_enum, default_value_t)]
+        format: OutputFormat,
+        /// File of answers, in toml or json, keyed by question id. Answered questions are not asked.
+        #[clap(long)]
+        answers: Option<PathBuf>,
+        /// Answer a question, e.g. `--set name=my_app`. Can be repeated.
+        #[clap(long = "set", value_parser = parse_answer)]
+        set: Vec<(String, String)>,
+        /// Render again whenever the template changes, reusing the answers
+        #[clap(long)]
+        watch: bool,
+        /// Print nothing after a successful render
+        #[clap(short, long, conflicts_with = "verbose")]
+        quiet: bool,
+        /// Also list unchanged files and the template file each file came from
+        #[clap(short, long)]
+        verbose: bool,
     },
     /// Register a template source directory. All templates within this directory (recursive) will be available.
     Register {
         /// Path to the source directory to register
         path: PathBuf,
+        /// Name of the source. Templates can then be addressed as `<name>/<template>`. Defaults to the directory name.
+        #[clap(long)]
+        name: Option<String>,
+        /// How many directories deep to search for templates. Defaults to 4.
+        #[clap(long)]
+        max_depth: Option<usize>,
+        /// Only consider directories matching this glob, relative to the source, as templates. Can be repeated.
+        #[clap(long)]
+        include: Vec<String>,
+        /// Do not search directories matching this glob, relative to the source. Can be repeated.
+        #[clap(long)]
+        exclude: Vec<String>,
+        /// Also search directories ignored by `.gitignore` files
+        #[clap(long)]
+        search_gitignored: bool,
     },
     /// Remove a registered source directory
     Remove {
-        /// Path to the source directory to remove
-        path: PathBuf,
+        /// Name or path of the source directory to remove
+        source: String,
     },
     /// List registered templates
+    List {
+        /// Only list templates whose name, description or tags contain this text
+        query: Option<String>,
+        /// Only list templates with this tag. Can be repeated.
+        #[clap(long)]
+        tag: Vec<String>,
+        /// Search every source for templates again, rather than using the index from previous runs
+        #[clap(long)]
+        refresh: bool,
+        #[clap(long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Interactively create a new template
+    New {
+        /// Directory to create the template in
+        directory: PathBuf,
+    },
+    /// Create a template from an existing project, replacing the given values with questions
+    Extract {
+        /// Path to the project
+        project: PathBuf,
+        /// Directory to create the template in
+        template_dir: PathBuf,
+        /// A value to replace, e.g. `--var crate_name=my_server`. Every occurrence in file contents and path names
+        /// becomes `{{ crate_name }}`. Can be repeated.
+        #[clap(long = "var", value_parser = scaffold::parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    /// Show the metadata, questions and files of a template in the registry
+    Info {
+        /// The template name in the registry
+        name: String,
+    },
+    /// Undo the last render into a destination that was done with --overwrite-conflicts
+    Undo {
+        /// Path to the destination folder
+        #[clap(default_value = ".")]
+        destination: PathBuf,
+    },
+    /// Manage default answers, which are pre-filled when questions are asked
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Set the default answer to a question
+    Set {
+        /// The question id
+        id: String,
+        /// The answer. A `bool` is answered with `true` or `false` and a `multi-select` with comma separated choice ids.
+        value: String,
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Print the default answer to a question
+    Get {
+        /// The question id
+        id: String,
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Remove the default answer to a question
+    Unset {
+        /// The question id
+        id: String,
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// List every default answer
     List,
 }
 
+/// Which templates a default answer applies to. Every template when neither is given.
+#[derive(Args)]
+struct ScopeArgs {
+    /// Only for this template, addressed as in `use`
+    #[clap(long, conflicts_with = "source")]
+    template: Option<String>,
+    /// Only for the templates of this source
+    #[clap(long)]
+    source: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct TemplateConfig {
     #[serde(default)]
     meta: MetaConfig,
     #[serde(default)]
     questions: Vec<Question>,
+    #[serde(default)]
+    files: FilesConfig,
 }
 
 #[derive(Debug, Deserialize, Default)]
 struct MetaConfig {
     description: Option<String>,
     name: Option<String>,
+    /// Keywords to find the template by
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+    version: Option<String>,
+    homepage: Option<String>,
+    license: Option<String>,
+    /// The oldest stamp version able to render the template
+    min_stamp_version: Option<String>,
+    /// Rendered and printed after a successful render, e.g. `cd {{ name }} && cargo run`
+    post_message: Option<String>,
+    #[serde(default)]
+    delimiters: Delimiters,
 }
 
-#[derive(Debug, Deserialize)]
+/// Globs, relative to the template root, selecting which paths get special treatment. Omitted globs select nothing.
+#[derive(Debug, Deserialize, Default)]
+struct FilesConfig {
+    /// Rendered files whose permissions (e.g. the executable bit) are copied to the output. Copied files always keep
+    /// their permissions.
+    #[serde(default)]
+    preserve_permissions: Vec<String>,
+    /// Symlinks that are recreated, with rendered targets, rather than replaced by a copy of what they point to
+    #[serde(default)]
+    preserve_symlinks: Vec<String>,
+    /// Empty directories that are created in the output
+    #[serde(default)]
+    preserve_empty_dirs: Vec<String>,
+    /// Paths copied as is. Their contents are not rendered and their names are not interpolated.
+    #[serde(default)]
+    copy_without_render: Vec<String>,
+    /// Files rendered with tera even though their name does not include `.tera`
+    #[serde(default)]
+    render: Vec<String>,
+    /// Paths left out of the output, in addition to those in `.stampignore` files
+    #[serde(default)]
+    exclude: Vec<String>,
+    /// Whether paths ignored by the template's `.gitignore` files are left out of the output
+    #[serde(default)]
+    respect_gitignore: bool,
+    /// Files generated once per item of a list answer
+    #[serde(default)]
+    for_each: Vec<ForEachRule>,
+}
+
+/// Generates the files matching `path` once per item of the `items` answer, with the item bound to `as`, e.g.
+/// `src/handlers/{{ endpoint.name }}.rs`
+#[derive(Debug, Deserialize, Clone)]
+struct ForEachRule {
+    /// Glob, relative to the template root, of the files generated per item
+    path: String,
+    /// Id of the answer holding the items, usually a `list` question
+    items: String,
+    /// Name of the variable each item is bound to
+    #[serde(rename = "as", default = "default_item_variable")]
+    variable: String,
+}
+
+fn default_item_variable() -> String {
+    "item".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct Question {
     id: String,
     #[serde(rename = "type")]
     kind: QuestionType,
     prompt: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     default: Option<toml::Value>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     options: Option<Vec<String>>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     choices: Option<Vec<MultiChoice>>,
+    /// The questions asked for each item of a `list`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    questions: Option<Vec<Question>>,
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 enum QuestionType {
     String,
     Select,
     MultiSelect,
     Bool,
+    /// Any number of items, each answering the sub-questions. Rendered as an array of objects.
+    List,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 struct MultiChoice {
     id: String,
     prompt: String,
@@ -103,9 +323,114 @@ struct MultiChoice {
     default: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
-struct Registry {
-    sources: Vec<PathBuf>,
+/// A file, symlink or empty directory within a template
+struct TemplateEntry {
+    source: PathBuf,
+    /// Path relative to the template root, before interpolation
+    relative: PathBuf,
+    /// Ids of the answers the output path and rendered contents depend on
+    dependencies: Vec<String>,
+    /// For each component of `relative`, whether it is kept as is rather than interpolated
+    verbatim_components: Vec<bool>,
+    kind: EntryKind,
+    /// Set when the entry is generated once per item of an answer
+    for_each: Option<ForEachRule>,
+}
+
+enum EntryKind {
+    File {
+        /// Whether the contents are rendered with tera
+        is_tera: bool,
+        /// Whether the file name includes `.tera`, which is removed from the output name
+        has_tera_suffix: bool,
+        preserve_permissions: bool,
+    },
+    Symlink {
+        target: PathBuf,
+        render_target: bool,
+    },
+    EmptyDir,
+}
+
+impl TemplateEntry {
+    fn has_tera_suffix(&self) -> bool {
+        matches!(
+            self.kind,
+            EntryKind::File {
+                has_tera_suffix: true,
+                ..
+            }
+        )
+    }
+}
+
+/// The compiled globs of a [`FilesConfig`]
+struct FileRules {
+    preserve_permissions: GlobSet,
+    preserve_symlinks: GlobSet,
+    preserve_empty_dirs: GlobSet,
+    copy_without_render: GlobSet,
+    render: GlobSet,
+    exclude: GlobSet,
+    /// One glob per [`ForEachRule`], in order
+    for_each: GlobSet,
+}
+
+/// Gitignore style file listing template paths that are left out of the output
+const STAMP_IGNORE_FILE: &str = ".stampignore";
+/// File at the template root that is rendered and printed after a render, rather than written
+const NEXT_STEPS_FILE: &str = "NEXT_STEPS.md.tera";
+
+/// State carried between the renders of `stamp from --watch`
+#[derive(Default)]
+struct RenderSession {
+    /// Answers given so far. Questions that already have an answer are not asked again.
+    answers: tera::Context,
+    /// Files written by previous renders, which are replaced rather than treated as conflicts
+    written: HashSet<PathBuf>,
+    /// Default answers from the user config and `git config`, pre-filled when questions are asked
+    defaults: HashMap<String, toml::Value>,
+    /// Ids answered by the answers file and `--set`, which must be questions or choices of the template
+    provided: Vec<String>,
+}
+
+impl RenderSession {
+    /// Starts with the answers of an answers file and `--set` arguments, the latter taking precedence. Answers are
+    /// checked against their questions when the template is rendered.
+    fn with_answers(answers_file: Option<&Path>, set: Vec<(String, String)>) -> eros::Result<Self> {
+        let mut answers = serde_json::Map::new();
+        if let Some(path) = answers_file {
+            let contents = fs::read_to_string(path)
+                .with_context(|| format!("could not read `{}`", path.to_string_lossy()))?;
+            let invalid = || format!("Answers from `{}` are not valid", path.to_string_lossy());
+            answers = if path.extension().is_some_and(|ext| ext == "json") {
+                serde_json::from_str(&contents).with_context(invalid)?
+            } else {
+                let table: toml::Table = toml::from_str(&contents).with_context(invalid)?;
+                serde_json::to_value(table)?
+                    .as_object()
+                    .cloned()
+                    .unwrap_or_default()
+            };
+        }
+        for (id, value) in set {
+            answers.insert(id, serde_json::Value::String(value));
+        }
+        Ok(RenderSession {
+            provided: answers.keys().cloned().collect(),
+            answers: tera::Context::from_value(serde_json::Value::Object(answers))?,
+            written: HashSet::new(),
+            defaults: HashMap::new(),
+        })
+    }
+}
+
+/// Parses a `--set <id>=<value>` argument
+fn parse_answer(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((id, value)) if !id.is_empty() => Ok((id.to_string(), value.to_string())),
+        _ => Err(format!("`{}` should be of the form `<id>=<value>`", arg)),
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -115,15 +440,171 @@ enum ConflictStrategy {
     Skip,
 }
 
+/// How commands print their results. `json` and `toml` are meant for other tools.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+enum OutputFormat {
+    #[default]
+    Plain,
+    Json,
+    Toml,
+}
+
+/// How much `use` and `from` print after a successful render in plain format
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+impl Verbosity {
+    fn from_flags(quiet: bool, verbose: bool) -> Self {
+        if quiet {
+            Verbosity::Quiet
+        } else if verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        }
+    }
+}
+
+/// Exit code of errors other than those below. Invalid arguments exit with 2.
+const EXIT_ERROR: i32 = 1;
+/// Exit code when files already exist in the destination and no conflict option was given
+const EXIT_CONFLICTS: i32 = 3;
+/// Exit code when the template's configuration is invalid or its files fail to render
+const EXIT_TEMPLATE_ERROR: i32 = 4;
+
+/// An error along with the exit code it is reported with
+struct Failure {
+    exit_code: i32,
+    error: Box<dyn std::fmt::Debug>,
+}
+
+impl Failure {
+    /// The template's `stamp.toml` is invalid, it requires a newer stamp, or its files or paths failed to render
+    fn template(error: impl std::fmt::Debug + 'static) -> Self {
+        Failure {
+            exit_code: EXIT_TEMPLATE_ERROR,
+            error: Box::new(error),
+        }
+    }
+
+    /// Files already exist in the destination and no conflict option was given
+    fn conflicts(error: impl std::fmt::Debug + 'static) -> Self {
+        Failure {
+            exit_code: EXIT_CONFLICTS,
+            error: Box::new(error),
+        }
+    }
+}
+
+impl<E: std::fmt::Debug + 'static> From<E> for Failure {
+    fn from(error: E) -> Self {
+        Failure {
+            exit_code: EXIT_ERROR,
+            error: Box::new(error),
+        }
+    }
+}
+
+/// What a render did with a file
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FileStatus {
+    Created,
+    Overwritten,
+    /// The existing file already had the rendered content
+    Unchanged,
+    Skipped,
+    /// Written by a previous render of a watch session, but no longer produced by the template
+    Removed,
+}
+
+/// A file in the summary printed after a render
+struct SummaryFile {
+    /// Relative to the destination
+    path: PathBuf,
+    status: FileStatus,
+    entry_kind: &'static str,
+    /// Relative to the template. Removed files have none.
+    source: Option<PathBuf>,
+}
+
+/// The files a render wrote or left alone
+#[derive(Debug, Serialize)]
+struct RenderReport {
+    template: PathBuf,
+    destination: PathBuf,
+    created: Vec<PathBuf>,
+    overwritten: Vec<PathBuf>,
+    skipped: Vec<PathBuf>,
+    /// Existing files that already had the rendered content
+    unchanged: Vec<PathBuf>,
+    /// Files whose contents were rendered with tera. The others were copied as is.
+    rendered: Vec<PathBuf>,
+    /// Files written by a previous render of a watch session that the template no longer produces
+    removed: Vec<PathBuf>,
+    /// Where the overwritten files were backed up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backup: Option<PathBuf>,
+    /// The template's rendered post message and next steps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_steps: Option<String>,
+}
+
+/// A template as listed by `stamp list --format json|toml`
+#[derive(Debug, Serialize)]
+struct TemplateSummary {
+    name: String,
+    qualified_name: String,
+    path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    source: String,
+    tags: Vec<String>,
+    authors: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    homepage: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license: Option<String>,
+    questions: Vec<Question>,
+}
+
+#[derive(Debug, Serialize)]
+struct TemplateListing {
+    templates: Vec<TemplateSummary>,
+    warnings: Vec<String>,
+}
+
 fn main() -> eros::Result<()> {
     let cli = Cli::parse();
 
-    let result = match cli.command {
+    if let Err(failure) = run(cli) {
+        eprintln!("Oops something went wrong.\n");
+        eprintln!("{:?}", failure.error);
+        exit(failure.exit_code);
+    };
+
+    Ok(())
+}
+
+fn run(cli: Cli) -> Result<(), Failure> {
+    let registry = cli.registry.as_deref();
+
+    match cli.command {
         Commands::Use {
             name,
             destination,
             overwrite_conflicts,
             skip_conflicts,
+            format,
+            answers,
+            set,
+            quiet,
+            verbose,
         } => {
             let strategy = if overwrite_conflicts {
                 ConflictStrategy::Overwrite
@@ -132,13 +613,28 @@ fn main() -> eros::Result<()> {
             } else {
                 ConflictStrategy::Fail
             };
-            render_registered_template(name, destination, strategy)
+            let mut session = RenderSession::with_answers(answers.as_deref(), set)?;
+            render_registered_template(
+                name,
+                destination,
+                strategy,
+                format,
+                Verbosity::from_flags(quiet, verbose),
+                registry,
+                &mut session,
+            )?;
         }
         Commands::From {
             source,
             destination,
             overwrite_conflicts,
             skip_conflicts,
+            format,
+            answers,
+            set,
+            watch,
+            quiet,
+            verbose,
         } => {
             let strategy = if overwrite_conflicts {
                 ConflictStrategy::Overwrite
@@ -147,33 +643,134 @@ fn main() -> eros::Result<()> {
             } else {
                 ConflictStrategy::Fail
             };
-            render_template(source, destination, strategy)
+            let verbosity = Verbosity::from_flags(quiet, verbose);
+            let mut session = RenderSession::with_answers(answers.as_deref(), set)?;
+            session.defaults = config::default_answers(None)?;
+            if watch {
+                watch::watch_template(source, destination, strategy, format, verbosity, session)?;
+            } else {
+                render_template(
+                    source,
+                    destination,
+                    strategy,
+                    format,
+                    verbosity,
+                    &mut session,
+                )?;
+            }
+        }
+        Commands::Register {
+            path,
+            name,
+            max_depth,
+            include,
+            exclude,
+            search_gitignored,
+        } => {
+            let options = SourceOptions {
+                max_depth,
+                include,
+                exclude,
+                search_gitignored: search_gitignored.then_some(true),
+            };
+            let options = (options != SourceOptions::default()).then_some(options);
+            registry::register_source(path, name, options, registry)?;
         }
-        Commands::Register { path } => register_source(path),
-        Commands::Remove { path } => remove_source(path),
-        Commands::List => list_templates(),
-    };
-
-    if let Err(error) = result {
-        eprintln!("Oops something went wrong.\n");
-        eprintln!("{:?}", error);
-        exit(1);
-    };
+        Commands::Remove { source } => registry::remove_source(source, registry)?,
+        Commands::List {
+            query,
+            tag,
+            refresh,
+            format,
+        } => list_templates(registry, query.as_deref(), &tag, refresh, format)?,
+        Commands::New { directory } => scaffold::new_template(directory, registry)?,
+        Commands::Extract {
+            project,
+            template_dir,
+            vars,
+        } => scaffold::extract_template(project, template_dir, vars)?,
+        Commands::Info { name } => show_template_info(&name, registry)?,
+        Commands::Undo { destination } => backup::undo(destination)?,
+        Commands::Config { command } => match command {
+            ConfigCommand::Set { id, value, scope } => {
+                let scope = config::Scope::resolve(
+                    scope.template.as_deref(),
+                    scope.source.as_deref(),
+                    registry,
+                )?;
+                config::set_default(id, value, scope)?;
+            }
+            ConfigCommand::Get { id, scope } => {
+                let scope = config::Scope::resolve(
+                    scope.template.as_deref(),
+                    scope.source.as_deref(),
+                    registry,
+                )?;
+                config::get_default(&id, scope)?;
+            }
+            ConfigCommand::Unset { id, scope } => {
+                let scope = config::Scope::resolve(
+                    scope.template.as_deref(),
+                    scope.source.as_deref(),
+                    registry,
+                )?;
+                config::unset_default(&id, scope)?;
+            }
+            ConfigCommand::List => config::list_defaults()?,
+        },
+    }
 
     Ok(())
 }
 
 fn render_registered_template(
-    template_name: String,
+    template_name: Option<String>,
     destination_path: PathBuf,
     conflict_strategy: ConflictStrategy,
-) -> eros::Result<()> {
-    let registry = load_registry()?;
-    let templates = find_templates(&registry.sources);
+    format: OutputFormat,
+    verbosity: Verbosity,
+    registry_override: Option<&Path>,
+    session: &mut RenderSession,
+) -> Result<(), Failure> {
+    let selected = resolve_template(template_name.as_deref(), registry_override)?;
+    session.defaults = config::default_answers(Some(&selected))?;
+    render_template(
+        selected.path,
+        destination_path,
+        conflict_strategy,
+        format,
+        verbosity,
+        session,
+    )
+}
+
+/// Finds the registered template `template_name` refers to, by name, path suffix or `<source>/<template>`. When no
+/// name is given, or it is ambiguous, the template is picked interactively if a terminal is attached.
+fn resolve_template(
+    template_name: Option<&str>,
+    registry_override: Option<&Path>,
+) -> eros::Result<FoundTemplate> {
+    let registry = load_registry(registry_override)?;
+    let templates = find_templates(&registry.sources, false).templates;
+
+    let Some(template_name) = template_name else {
+        if templates.is_empty() {
+            bail!("No templates found in registered sources");
+        }
+        if !Term::stderr().is_term() {
+            bail!("No template name given. Provide one, or run in a terminal to pick a template.");
+        }
+        let all: Vec<&FoundTemplate> = templates.iter().collect();
+        return pick_template(&all, "Select a template");
+    };
 
     let mut matches: Vec<&FoundTemplate> = Vec::new();
 
-    let query_path = PathBuf::from(&template_name);
+    let query_path = PathBuf::from(template_name);
+    // `<source>/<template>` addresses a template within a single source
+    let namespaced = template_name
+        .split_once('/')
+        .map(|(namespace, rest)| (namespace, rest, PathBuf::from(rest)));
 
     for template in &templates {
         if template.name == template_name {
@@ -185,517 +782,2031 @@ fn render_registered_template(
             matches.push(template);
             continue;
         }
+
+        if let Some((namespace, rest, rest_path)) = &namespaced
+            && template.source.namespace() == *namespace
+            && (template.name == *rest
+                || template
+                    .path
+                    .strip_prefix(&template.source.path)
+                    .is_ok_and(|relative| relative.ends_with(rest_path)))
+        {
+            matches.push(template);
+            continue;
+        }
     }
 
     matches.sort_by_key(|t| &t.path);
     matches.dedup_by_key(|t| &t.path);
 
     if matches.is_empty() {
-        bail!("Template '{}' not found in registry", template_name)
+        let suggestions = similar_templates(template_name, &templates);
+        if suggestions.is_empty() {
+            bail!("Template '{}' not found in registry", template_name)
+        }
+        bail!(
+            "Template '{}' not found in registry. Did you mean {}?",
+            template_name,
+            suggestions
+                .iter()
+                .map(|s| format!("'{}'", s))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    } else if matches.len() > 1 && Term::stderr().is_term() {
+        return pick_template(
+            &matches,
+            &format!("'{}' matches several templates", template_name),
+        );
     } else if matches.len() > 1 {
         eprintln!("Ambiguous template match for '{}':", template_name);
         for m in matches {
-            eprintln!(" - {} ({})", m.name, m.path.to_string_lossy());
+            eprintln!(
+                " - {} ({}) {}",
+                m.name,
+                m.qualified_name(),
+                m.path.to_string_lossy()
+            );
         }
         bail!("Please provide a more specific path or name.");
     }
 
-    let selected = matches[0];
-    render_template(selected.path.clone(), destination_path, conflict_strategy)
+    Ok(matches[0].clone())
+}
+
+fn pick_template(candidates: &[&FoundTemplate], prompt: &str) -> eros::Result<FoundTemplate> {
+    let items: Vec<String> = candidates
+        .iter()
+        .map(|template| match &template.description {
+            Some(description) => format!(
+                "{} - {} ({})",
+                template.name,
+                description,
+                template.qualified_name()
+            ),
+            None => format!("{} ({})", template.name, template.qualified_name()),
+        })
+        .collect();
+    let selection = FuzzySelect::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(&items)
+        .default(0)
+        .interact()?;
+    Ok(candidates[selection].clone())
+}
+
+/// Names of the templates closest to `query`, best first
+fn similar_templates(query: &str, templates: &[FoundTemplate]) -> Vec<String> {
+    const MIN_SIMILARITY: f64 = 0.8;
+    const MAX_SUGGESTIONS: usize = 3;
+
+    let query = query.to_lowercase();
+    let mut scored: Vec<(f64, String)> = templates
+        .iter()
+        .filter_map(|template| {
+            let qualified_name = template.qualified_name();
+            let score = [&template.name, &qualified_name]
+                .into_iter()
+                .map(|candidate| strsim::jaro_winkler(&query, &candidate.to_lowercase()))
+                .fold(0.0, f64::max);
+            (score >= MIN_SIMILARITY).then_some((score, qualified_name))
+        })
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, name)| name)
+        .collect()
 }
 
 fn render_template(
     template_path: PathBuf,
     destination_path: PathBuf,
     conflict_strategy: ConflictStrategy,
-) -> eros::Result<()> {
-    let config_path = template_path.join("stamp.toml");
-    let config_contents = fs::read_to_string(&config_path)
-        .with_context(|| format!("could not read `{}`", config_path.to_string_lossy()))?;
-    let config: TemplateConfig = toml::from_str(&config_contents).with_context(|| {
-        format!(
-            "Template config from `{}` is not valid",
-            config_path.to_string_lossy()
-        )
-    })?;
+    format: OutputFormat,
+    verbosity: Verbosity,
+    session: &mut RenderSession,
+) -> Result<(), Failure> {
+    check_stamp_version(&template_path).map_err(Failure::template)?;
+    let config = load_config(&template_path).map_err(Failure::template)?;
+    validate_config(&config).map_err(Failure::template)?;
+    check_provided_answers(&config.questions, &session.provided)?;
+
+    let mut renderer = Renderer::new(config.meta.delimiters.clone());
+
+    let entries = collect_entries(&template_path, &config, Some(&destination_path))
+        .map_err(Failure::template)?;
+
+    // Each path is checked for conflicts as soon as the answers it depends on are known, so conflicts surface
+    // before the remaining questions are asked
+    // Entries generated per item are only checked once every action is known
+    let mut unchecked: Vec<&TemplateEntry> = if conflict_strategy == ConflictStrategy::Fail {
+        entries
+            .iter()
+            .filter(|entry| entry.for_each.is_none())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let mut answered: HashSet<&str> = HashSet::new();
+    let mut context = session.answers.clone();
+    check_early_conflicts(
+        &mut unchecked,
+        &answered,
+        &destination_path,
+        &mut renderer,
+        &context,
+        &session.written,
+    )?;
 
-    let mut validation_errors = Vec::new();
-    for question in &config.questions {
-        if question.options.is_some() && question.choices.is_some() {
-            validation_errors.push(format!(
-                "Question '{}' cannot have both 'options' and 'choices'",
-                question.id
-            ));
+    let total_questions = config.questions.len();
+    for (i, question) in config.questions.iter().enumerate() {
+        let step = i + 1;
+        let prompt = format!("[{}/{}] {}", step, total_questions, question.prompt);
+        // Defaults are only looked up for questions that are asked, which keeps `git config` from running otherwise
+        let prefilled = if context.get(&question.id).is_none() {
+            session
+                .defaults
+                .get(&question.id)
+                .cloned()
+                .or_else(|| config::git_default(&question.id))
+                .and_then(|default| with_user_default(question, &default))
+        } else {
+            None
+        };
+        answer_question(
+            prefilled.as_ref().unwrap_or(question),
+            &prompt,
+            &mut context,
+        )?;
+
+        answered.insert(&question.id);
+        if let Some(choices) = &question.choices {
+            answered.extend(choices.iter().map(|c| c.id.as_str()));
         }
+        check_early_conflicts(
+            &mut unchecked,
+            &answered,
+            &destination_path,
+            &mut renderer,
+            &context,
+            &session.written,
+        )?;
+    }
+    session.answers = context.clone();
+
+    let actions = plan_actions(&entries, &destination_path, &mut renderer, &context)
+        .map_err(Failure::template)?;
+
+    // Everything is rendered into a staging directory first, so a failed render leaves the destination untouched
+    let staging = StagingDir::create()?;
+    let mut staged: Vec<Result<(), String>> = Vec::new();
+    for (index, action) in actions.iter().enumerate() {
+        let staged_path = staging.path.join(index.to_string());
+        let context = action.item_context.as_ref().unwrap_or(&context);
+        staged.push(stage_entry(
+            action.entry,
+            &staged_path,
+            &action.destination,
+            &destination_path,
+            &mut renderer,
+            context,
+        ));
+    }
 
-        match question.kind {
-            QuestionType::Select => {
-                if question.options.is_none() {
-                    validation_errors.push(format!(
-                        "Question '{}' of type 'select' must have 'options'",
-                        question.id
-                    ));
-                }
-                if question.choices.is_some() {
-                    validation_errors.push(format!(
-                        "Question '{}' of type 'select' cannot have 'choices'",
-                        question.id
-                    ));
-                }
-            }
-            QuestionType::MultiSelect => {
-                if question.choices.is_none() {
-                    validation_errors.push(format!(
-                        "Question '{}' of type 'multi-select' must have 'choices'",
-                        question.id
-                    ));
-                }
-                if question.options.is_some() {
-                    validation_errors.push(format!(
-                        "Question '{}' of type 'multi-select' cannot have 'options'",
-                        question.id
-                    ));
-                }
-            }
-            QuestionType::String | QuestionType::Bool => {
-                if question.options.is_some() {
-                    validation_errors.push(format!(
-                        "Question '{}' of type '{:?}' cannot have 'options'",
-                        question.id, question.kind
-                    ));
-                }
-                if question.choices.is_some() {
-                    validation_errors.push(format!(
-                        "Question '{}' of type '{:?}' cannot have 'choices'",
-                        question.id, question.kind
-                    ));
+    // Existing files with the rendered content are left alone rather than treated as conflicts. Files written by a
+    // previous render of the session are replaced.
+    let written = &session.written;
+    let mut statuses: Vec<FileStatus> = Vec::new();
+    let mut conflicts = Vec::new();
+    for (index, action) in actions.iter().enumerate() {
+        let staged_path = staging.path.join(index.to_string());
+        let status = if action.destination.symlink_metadata().is_err() {
+            FileStatus::Created
+        } else if !is_conflict(action.entry, &action.destination)
+            || (staged[index].is_ok()
+                && is_unchanged(action.entry, &staged_path, &action.destination))
+        {
+            FileStatus::Unchanged
+        } else if written.contains(&action.destination) {
+            FileStatus::Overwritten
+        } else {
+            match conflict_strategy {
+                ConflictStrategy::Fail => {
+                    conflicts.push(action.destination.clone());
+                    FileStatus::Skipped
                 }
+                ConflictStrategy::Skip => FileStatus::Skipped,
+                ConflictStrategy::Overwrite => FileStatus::Overwritten,
             }
+        };
+        statuses.push(status);
+    }
+    report_conflicts(conflicts).map_err(Failure::conflicts)?;
+
+    // Skipped files are not written, so they cannot fail the render
+    let mut render_errors = Vec::new();
+    for ((action, result), status) in actions.iter().zip(staged).zip(&statuses) {
+        if let Err(error) = result
+            && *status != FileStatus::Skipped
+        {
+            render_errors.push((action.entry.source.clone(), error));
         }
     }
 
-    if !validation_errors.is_empty() {
-        eprintln!("Invalid template configuration:");
-        for error in validation_errors {
-            eprintln!(" - {}", error);
+    // Rendered before anything is written, so errors in it also leave the destination untouched
+    let mut next_steps = Vec::new();
+    if let Some(post_message) = &config.meta.post_message {
+        match renderer.render_str(post_message, &context) {
+            Ok(message) => next_steps.push(message),
+            Err(error) => {
+                render_errors.push((template_path.join("stamp.toml"), error_chain(&error)))
+            }
+        }
+    }
+    let next_steps_path = template_path.join(NEXT_STEPS_FILE);
+    if next_steps_path.is_file() {
+        let rendered = fs::read_to_string(&next_steps_path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| {
+                renderer
+                    .render_str(&text, &context)
+                    .map_err(|e| error_chain(&e))
+            });
+        match rendered {
+            Ok(message) => next_steps.push(message),
+            Err(error) => render_errors.push((next_steps_path, error)),
         }
-        bail!("Template configuration validation failed");
     }
+    let next_steps = (!next_steps.is_empty()).then(|| {
+        next_steps
+            .iter()
+            .map(|message| message.trim())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    });
+
+    report_render_errors(render_errors).map_err(Failure::template)?;
+
+    // Later renders of a session only need a backup when they overwrite files the session did not write
+    let overwrites_others = actions.iter().zip(&statuses).any(|(action, status)| {
+        *status == FileStatus::Overwritten && !written.contains(&action.destination)
+    });
+    let mut backup = if conflict_strategy == ConflictStrategy::Overwrite
+        && (written.is_empty() || overwrites_others)
+    {
+        Some(backup::Backup::start(&destination_path, &template_path)?)
+    } else {
+        None
+    };
 
-    if conflict_strategy == ConflictStrategy::Fail {
-        let mut early_conflicts = Vec::new();
-        for entry in walkdir::WalkDir::new(&template_path) {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() {
-                if path.file_name().is_some_and(|n| n == "stamp.toml") {
-                    continue;
+    // Unchanged files are not rewritten, which keeps their modification times
+    let write_result = (|| -> eros::Result<()> {
+        for (index, (action, status)) in actions.iter().zip(&statuses).enumerate() {
+            if matches!(status, FileStatus::Unchanged | FileStatus::Skipped) {
+                continue;
+            }
+            if let EntryKind::EmptyDir = action.entry.kind {
+                fs::create_dir_all(&action.destination)?;
+                if let Some(backup) = &mut backup
+                    && *status == FileStatus::Created
+                {
+                    backup.record_created_dir(&action.destination)?;
                 }
+                continue;
+            }
 
-                let relative = path.strip_prefix(&template_path)?;
-                let relative_str = relative.to_string_lossy();
+            if let Some(parent) = action.destination.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let rewritten = written.contains(&action.destination);
+            if let Some(backup) = &mut backup
+                && !rewritten
+            {
+                backup.save_original(&action.destination)?;
+            }
 
-                if relative_str.contains("{{") {
-                    // skip interpolation since these will be replaced and we don't know what the output will look like
-                    continue;
-                }
-                let mut output_path = destination_path.join(relative);
-                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
-                let is_tera = file_name.ends_with(".tera") || file_name.contains(".tera.");
-                if is_tera {
-                    let new_name = output_path
-                        .file_name()
-                        .unwrap_or_default()
-                        .to_string_lossy()
-                        .replace(".tera", "");
-                    output_path.set_file_name(new_name);
-                }
+            let staged_path = staging.path.join(index.to_string());
+            move_file(&staged_path, &action.destination).with_context(|| {
+                format!("Could not write `{}`", action.destination.to_string_lossy())
+            })?;
 
-                if output_path.exists() {
-                    early_conflicts.push(output_path);
-                }
+            if let Some(backup) = &mut backup
+                && !rewritten
+            {
+                backup.record_written(&action.destination)?;
             }
         }
+        Ok(())
+    })();
+    // Written even on failure so a partial render can still be undone
+    let backup_dir = match backup {
+        Some(backup) => backup.finish()?,
+        None => None,
+    };
+    write_result?;
+    let produced: HashSet<PathBuf> = actions
+        .iter()
+        .zip(&statuses)
+        .filter(|(_, status)| **status != FileStatus::Skipped)
+        .map(|(action, _)| action.destination.clone())
+        .collect();
+    let removed = remove_stale_outputs(&session.written, &produced)?;
+    session.written = produced;
+
+    if format != OutputFormat::Plain {
+        let with_status = |status: FileStatus| {
+            actions
+                .iter()
+                .zip(&statuses)
+                .filter(|(_, s)| **s == status)
+                .map(|(action, _)| action.destination.clone())
+                .collect()
+        };
+        let report = RenderReport {
+            created: with_status(FileStatus::Created),
+            overwritten: with_status(FileStatus::Overwritten),
+            skipped: with_status(FileStatus::Skipped),
+            unchanged: with_status(FileStatus::Unchanged),
+            removed: removed.iter().map(|(path, _)| path.clone()).collect(),
+            rendered: actions
+                .iter()
+                .zip(&statuses)
+                .filter(|(action, status)| {
+                    **status != FileStatus::Skipped
+                        && matches!(action.entry.kind, EntryKind::File { is_tera: true, .. })
+                })
+                .map(|(action, _)| action.destination.clone())
+                .collect(),
+            template: template_path,
+            destination: destination_path,
+            backup: backup_dir,
+            next_steps,
+        };
+        print_formatted(&report, format)?;
+        return Ok(());
+    }
 
-        if !early_conflicts.is_empty() {
-            eprintln!("Conflicting files found:");
-            for conflict in early_conflicts {
-                eprintln!(" - {}", conflict.to_string_lossy());
-            }
-            bail!("Destination files already exist. Use --overwrite-conflicts or --skip-conflicts to resolve.");
-        }
+    if verbosity == Verbosity::Quiet {
+        return Ok(());
     }
+    let relative = |path: &Path| {
+        path.strip_prefix(&destination_path)
+            .unwrap_or(path)
+            .to_path_buf()
+    };
+    let mut summary: Vec<SummaryFile> = actions
+        .iter()
+        .zip(&statuses)
+        .map(|(action, status)| SummaryFile {
+            path: relative(&action.destination),
+            status: *status,
+            entry_kind: entry_kind_name(&action.entry.kind),
+            source: Some(action.entry.relative.clone()),
+        })
+        .collect();
+    summary.extend(removed.iter().map(|(path, entry_kind)| SummaryFile {
+        path: relative(path),
+        status: FileStatus::Removed,
+        entry_kind,
+        source: None,
+    }));
 
-    let mut context = tera::Context::new();
+    println!("Template rendered successfully to {:?}", destination_path);
+    print_render_summary(&summary, verbosity);
+    if let Some(backup_dir) = backup_dir {
+        println!(
+            "Overwritten files were backed up to `{}`. Run `stamp undo` to restore them.",
+            backup_dir.to_string_lossy()
+        );
+    }
+    if let Some(next_steps) = next_steps {
+        println!();
+        println!("{}", next_steps);
+    }
+    Ok(())
+}
 
-    let total_questions = config.questions.len();
-    for (i, question) in config.questions.iter().enumerate() {
-        let step = i + 1;
-        let prompt = format!("[{}/{}] {}", step, total_questions, question.prompt);
-        let theme = ColorfulTheme::default();
+struct FileAction<'a> {
+    entry: &'a TemplateEntry,
+    destination: PathBuf,
+    /// The context with the item bound, for entries generated per item
+    item_context: Option<tera::Context>,
+}
 
-        match question.kind {
-            QuestionType::String => {
-                let default_val = question
-                    .default
-                    .as_ref()
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.to_string());
+/// Where each entry is written. Entries generated per item of a list answer get an action per item.
+fn plan_actions<'a>(
+    entries: &'a [TemplateEntry],
+    destination_path: &Path,
+    renderer: &mut Renderer,
+    context: &tera::Context,
+) -> eros::Result<Vec<FileAction<'a>>> {
+    let mut actions: Vec<FileAction> = Vec::new();
+    for entry in entries {
+        let Some(rule) = &entry.for_each else {
+            actions.push(FileAction {
+                entry,
+                destination: output_path(entry, destination_path, renderer, context)?,
+                item_context: None,
+            });
+            continue;
+        };
+
+        let Some(items) = context.get(&rule.items).and_then(|items| items.as_array()) else {
+            bail!(
+                "`{}` is generated for each item of `{}`, which is not a list answer",
+                entry.relative.to_string_lossy(),
+                rule.items
+            );
+        };
+        let mut generated: HashSet<PathBuf> = HashSet::new();
+        for item in items {
+            let mut item_context = context.clone();
+            item_context.insert(&rule.variable, item);
+            let destination = output_path(entry, destination_path, renderer, &item_context)?;
+            if !generated.insert(destination.clone()) {
+                bail!(
+                    "Several items of `{}` render `{}` to `{}`. Use `{}` in its path so each item gets its own file.",
+                    rule.items,
+                    entry.relative.to_string_lossy(),
+                    destination.to_string_lossy(),
+                    rule.variable
+                );
+            }
+            actions.push(FileAction {
+                entry,
+                destination,
+                item_context: Some(item_context),
+            });
+        }
+    }
+    Ok(actions)
+}
 
-                let mut input = Input::<String>::with_theme(&theme);
-                input = input.with_prompt(&prompt);
+/// The files, symlinks and empty directories of a template that are written to the output
+fn collect_entries(
+    template_path: &Path,
+    config: &TemplateConfig,
+    destination_path: Option<&Path>,
+) -> eros::Result<Vec<TemplateEntry>> {
+    let rules = FileRules::new(&config.files)?;
+
+    // The destination may be inside the template, e.g. while watching, and must not be copied into itself
+    let canonical_template = fs::canonicalize(template_path)?;
+    let destination_in_template = destination_path
+        .and_then(|destination| fs::canonicalize(destination).ok())
+        .and_then(|destination| {
+            destination
+                .strip_prefix(&canonical_template)
+                .ok()
+                .map(Path::to_path_buf)
+        })
+        .filter(|relative| !relative.as_os_str().is_empty());
+
+    let exclude = rules.exclude.clone();
+    let root = template_path.to_path_buf();
+    let walker = WalkBuilder::new(template_path)
+        .standard_filters(false)
+        .git_ignore(config.files.respect_gitignore)
+        .require_git(false)
+        .parents(false)
+        .add_custom_ignore_filename(STAMP_IGNORE_FILE)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(move |entry| {
+            // `.stamp` holds the backups of renders
+            let relative = entry.path().strip_prefix(&root).ok();
+            entry.file_name() != ".git"
+                && entry.file_name() != ".stamp"
+                && !relative.is_some_and(|relative| {
+                    exclude.is_match(relative)
+                        || destination_in_template
+                            .as_ref()
+                            .is_some_and(|destination| relative.starts_with(destination))
+                })
+        })
+        .build();
 
-                if let Some(default) = default_val {
-                    input = input.default(default);
-                }
+    let mut entries: Vec<TemplateEntry> = Vec::new();
+    for entry in walker {
+        let entry = entry?;
+        let path_in_template = entry.path();
+        let relative_path_in_template = path_in_template.strip_prefix(template_path)?;
+        let Some(file_type) = entry.file_type() else {
+            continue;
+        };
+        let copy_without_render = rules
+            .copy_without_render
+            .is_match(relative_path_in_template);
+
+        let kind = if file_type.is_symlink()
+            && rules.preserve_symlinks.is_match(relative_path_in_template)
+        {
+            EntryKind::Symlink {
+                target: fs::read_link(path_in_template)?,
+                render_target: !copy_without_render,
+            }
+        } else if path_in_template.is_file() {
+            if path_in_template
+                .file_name()
+                .is_some_and(|name| name == "stamp.toml" || name == STAMP_IGNORE_FILE)
+                || relative_path_in_template == Path::new(NEXT_STEPS_FILE)
+            {
+                continue;
+            }
 
-                let value = input.interact()?;
-                context.insert(&question.id, &value);
+            let file_name = path_in_template
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            let has_tera_suffix = !copy_without_render
+                && (file_name.ends_with(".tera") || file_name.contains(".tera."));
+            let is_tera = !copy_without_render
+                && (has_tera_suffix || rules.render.is_match(relative_path_in_template))
+                && !is_binary(path_in_template)?;
+            EntryKind::File {
+                is_tera,
+                has_tera_suffix,
+                preserve_permissions: rules
+                    .preserve_permissions
+                    .is_match(relative_path_in_template),
             }
-            QuestionType::Bool => {
-                let default_val = question
-                    .default
-                    .as_ref()
-                    .and_then(|v| v.as_bool())
-                    .unwrap_or(false);
+        } else if file_type.is_dir()
+            && entry.depth() > 0
+            && rules
+                .preserve_empty_dirs
+                .is_match(relative_path_in_template)
+            && fs::read_dir(path_in_template)?.next().is_none()
+        {
+            EntryKind::EmptyDir
+        } else {
+            continue;
+        };
+
+        let mut prefix = PathBuf::new();
+        let mut interpolated = PathBuf::new();
+        let mut verbatim_components = Vec::new();
+        for component in relative_path_in_template.components() {
+            prefix.push(component);
+            let verbatim = rules.copy_without_render.is_match(&prefix);
+            if !verbatim {
+                interpolated.push(component);
+            }
+            verbatim_components.push(verbatim);
+        }
 
-                let value = Confirm::with_theme(&theme)
-                    .with_prompt(&prompt)
-                    .default(default_val)
-                    .interact()?;
-                context.insert(&question.id, &value);
+        // The first matching rule applies
+        let for_each = rules
+            .for_each
+            .matches(relative_path_in_template)
+            .first()
+            .map(|&index| config.files.for_each[index].clone());
+        let mut dependencies =
+            path_dependencies(&interpolated, &config.questions, &config.meta.delimiters);
+        if let Some(rule) = &for_each {
+            dependencies.push(rule.items.clone());
+        }
+        // Rendered contents can only be compared with an existing file once their answers are known
+        let rendered_text = match &kind {
+            EntryKind::File { is_tera: true, .. } => fs::read_to_string(path_in_template).ok(),
+            EntryKind::Symlink {
+                target,
+                render_target: true,
+            } => Some(target.to_string_lossy().to_string()),
+            _ => None,
+        };
+        if let Some(text) = rendered_text {
+            for dependency in text_dependencies(&text, &config.questions, &config.meta.delimiters) {
+                if !dependencies.contains(&dependency) {
+                    dependencies.push(dependency);
+                }
             }
+        }
+        // The loop variable is bound to an item rather than answered, even if a question has the same id
+        if let Some(rule) = &for_each {
+            dependencies
+                .retain(|dependency| *dependency != rule.variable || *dependency == rule.items);
+        }
+
+        entries.push(TemplateEntry {
+            source: path_in_template.to_path_buf(),
+            relative: relative_path_in_template.to_path_buf(),
+            dependencies,
+            verbatim_components,
+            kind,
+            for_each,
+        });
+    }
+
+    Ok(entries)
+}
+
+fn validate_config(config: &TemplateConfig) -> eros::Result<()> {
+    let mut validation_errors = Vec::new();
+    validate_questions(&config.questions, &mut validation_errors);
+    validation_errors.extend(config.meta.delimiters.validate());
+
+    if !validation_errors.is_empty() {
+        eprintln!("Invalid template configuration:");
+        for error in validation_errors {
+            eprintln!(" - {}", error);
+        }
+        bail!("Template configuration validation failed");
+    }
+    Ok(())
+}
+
+/// Fails when an answers file or `--set` answers an id that is not a question, e.g. a typo. Choices are answered
+/// through their `multi-select` question.
+fn check_provided_answers(questions: &[Question], provided: &[String]) -> eros::Result<()> {
+    for id in provided {
+        if let Some(question) = questions.iter().find(|question| {
+            question
+                .choices
+                .iter()
+                .flatten()
+                .any(|choice| choice.id == *id)
+        }) {
+            bail!(
+                "`{}` is a choice of question `{}`. Answer the question instead, e.g. `--set {}={}`",
+                id,
+                question.id,
+                question.id,
+                id
+            );
+        }
+    }
+    let unknown: Vec<&str> = provided
+        .iter()
+        .filter(|id| !questions.iter().any(|question| question.id == **id))
+        .map(|id| id.as_str())
+        .collect();
+    if !unknown.is_empty() {
+        bail!(
+            "Answers were given for `{}`, which are not questions of the template",
+            unknown.join("`, `")
+        );
+    }
+    Ok(())
+}
+
+fn load_config(template_path: &Path) -> eros::Result<TemplateConfig> {
+    let config_path = template_path.join("stamp.toml");
+    let config_contents = fs::read_to_string(&config_path)
+        .with_context(|| format!("could not read `{}`", config_path.to_string_lossy()))?;
+    let config: TemplateConfig = toml::from_str(&config_contents).with_context(|| {
+        format!(
+            "Template config from `{}` is not valid",
+            config_path.to_string_lossy()
+        )
+    })?;
+    Ok(config)
+}
+
+/// Fails when the template requires a newer stamp. Only `min_stamp_version` is read, since the rest of the config may
+/// use features this version does not understand.
+fn check_stamp_version(template_path: &Path) -> eros::Result<()> {
+    #[derive(Deserialize)]
+    struct VersionedConfig {
+        #[serde(default)]
+        meta: VersionedMeta,
+    }
+    #[derive(Deserialize, Default)]
+    struct VersionedMeta {
+        min_stamp_version: Option<String>,
+    }
+
+    let config_path = template_path.join("stamp.toml");
+    let Ok(contents) = fs::read_to_string(&config_path) else {
+        // Reported when the config is loaded
+        return Ok(());
+    };
+    let Ok(config) = toml::from_str::<VersionedConfig>(&contents) else {
+        return Ok(());
+    };
+    let Some(min_version) = config.meta.min_stamp_version else {
+        return Ok(());
+    };
+
+    let requirement =
+        semver::VersionReq::parse(&format!(">={}", min_version)).with_context(|| {
+            format!(
+                "`min_stamp_version` of `{}` is not a valid version",
+                config_path.to_string_lossy()
+            )
+        })?;
+    let current = semver::Version::parse(env!("CARGO_PKG_VERSION"))?;
+    if !requirement.matches(&current) {
+        bail!(
+            "This template requires stamp {} or newer, but this is stamp {}. Upgrade with `cargo install stamp-cli`.",
+            min_version,
+            current
+        );
+    }
+    Ok(())
+}
+
+/// Prints `value` as json or toml
+fn print_formatted<T: Serialize>(value: &T, format: OutputFormat) -> eros::Result<()> {
+    let output = match format {
+        OutputFormat::Json => serde_json::to_string_pretty(value)?,
+        OutputFormat::Toml => toml::to_string_pretty(value)?,
+        OutputFormat::Plain => unreachable!("plain output is printed by each command"),
+    };
+    println!("{}", output);
+    Ok(())
+}
+
+/// Problems with the questions of a template, including the sub-questions of lists
+fn validate_questions(questions: &[Question], errors: &mut Vec<String>) {
+    for question in questions {
+        if question.options.is_some() && question.choices.is_some() {
+            errors.push(format!(
+                "Question '{}' cannot have both 'options' and 'choices'",
+                question.id
+            ));
+        }
+
+        if question.kind != QuestionType::List && question.questions.is_some() {
+            errors.push(format!(
+                "Question '{}' of type '{}' cannot have 'questions'",
+                question.id,
+                question_type_name(&question.kind)
+            ));
+        }
+
+        match question.kind {
             QuestionType::Select => {
-                if let Some(options) = &question.options {
-                    let default_idx = question
-                        .default
-                        .as_ref()
-                        .and_then(|v| v.as_str())
-                        .and_then(|d| options.iter().position(|r| r == d))
-                        .unwrap_or(0);
-
-                    let selection = Select::with_theme(&theme)
-                        .with_prompt(&prompt)
-                        .default(default_idx)
-                        .items(options)
-                        .interact()?;
-
-                    context.insert(&question.id, &options[selection]);
+                if question.options.is_none() {
+                    errors.push(format!(
+                        "Question '{}' of type 'select' must have 'options'",
+                        question.id
+                    ));
+                }
+                if question.choices.is_some() {
+                    errors.push(format!(
+                        "Question '{}' of type 'select' cannot have 'choices'",
+                        question.id
+                    ));
                 }
             }
             QuestionType::MultiSelect => {
-                if let Some(choices) = &question.choices {
-                    let defaults: Vec<bool> = choices.iter().map(|c| c.default).collect();
-                    let items: Vec<&String> = choices.iter().map(|c| &c.prompt).collect();
-
-                    let selections = MultiSelect::with_theme(&theme)
-                        .with_prompt(&prompt)
-                        .items(&items)
-                        .defaults(&defaults)
-                        .interact()?;
-
-                    for (idx, choice) in choices.iter().enumerate() {
-                        let is_selected = selections.contains(&idx);
-                        context.insert(&choice.id, &is_selected);
+                if question.choices.is_none() {
+                    errors.push(format!(
+                        "Question '{}' of type 'multi-select' must have 'choices'",
+                        question.id
+                    ));
+                }
+                if question.options.is_some() {
+                    errors.push(format!(
+                        "Question '{}' of type 'multi-select' cannot have 'options'",
+                        question.id
+                    ));
+                }
+            }
+            QuestionType::List => {
+                if question.options.is_some() || question.choices.is_some() {
+                    errors.push(format!(
+                        "Question '{}' of type 'list' cannot have 'options' or 'choices'",
+                        question.id
+                    ));
+                }
+                if question.default.is_some() {
+                    errors.push(format!(
+                        "Question '{}' of type 'list' cannot have a 'default'",
+                        question.id
+                    ));
+                }
+                match &question.questions {
+                    Some(questions) if !questions.is_empty() => {
+                        validate_questions(questions, errors);
                     }
-
-                    let selected_ids: Vec<&String> =
-                        selections.iter().map(|&idx| &choices[idx].id).collect();
-                    context.insert(&question.id, &selected_ids);
+                    _ => errors.push(format!(
+                        "Question '{}' of type 'list' must have 'questions'",
+                        question.id
+                    )),
+                }
+            }
+            QuestionType::String | QuestionType::Bool => {
+                if question.options.is_some() {
+                    errors.push(format!(
+                        "Question '{}' of type '{:?}' cannot have 'options'",
+                        question.id, question.kind
+                    ));
+                }
+                if question.choices.is_some() {
+                    errors.push(format!(
+                        "Question '{}' of type '{:?}' cannot have 'choices'",
+                        question.id, question.kind
+                    ));
                 }
             }
         }
     }
+}
 
-    let mut tera = Tera::default();
-    tera.autoescape_on(vec![]);
-    tera.set_escape_fn(|e| e.to_string());
+/// Uses the given answer to `question` if there is one, otherwise asks for it
+fn answer_question(
+    question: &Question,
+    prompt: &str,
+    context: &mut tera::Context,
+) -> eros::Result<()> {
+    match context.get(&question.id).cloned() {
+        Some(answer) => apply_answer(question, answer, context),
+        None => ask_question(question, prompt, context),
+    }
+}
 
-    struct FileAction {
-        source: PathBuf,
-        destination: PathBuf,
-        is_tera: bool,
+/// `question` with its default replaced by the user's default answer. Defaults that are not a valid answer to the
+/// question, or that are for a `list` question, are ignored with a warning.
+fn with_user_default(question: &Question, default: &toml::Value) -> Option<Question> {
+    // Items are always added one by one
+    if question.kind == QuestionType::List {
+        eprintln!(
+            "{}",
+            style(format!(
+                "Ignoring default answer from the user config for `{}`, since it is a list",
+                question.id
+            ))
+            .yellow()
+        );
+        return None;
     }
 
-    let mut actions: Vec<FileAction> = Vec::new();
+    let mut answered = tera::Context::new();
+    let answer = serde_json::to_value(default).ok()?;
+    if let Err(error) = apply_answer(question, answer, &mut answered) {
+        eprintln!(
+            "{}",
+            style(format!(
+                "Ignoring default answer from the user config: {:?}",
+                error
+            ))
+            .yellow()
+        );
+        return None;
+    }
 
-    for entry in walkdir::WalkDir::new(&template_path) {
-        let entry = entry?;
-        let path_in_template = entry.path();
+    let mut question = question.clone();
+    match question.kind {
+        QuestionType::String | QuestionType::Select => {
+            question.default = answered
+                .get(&question.id)
+                .and_then(|value| value.as_str())
+                .map(|value| toml::Value::String(value.to_string()));
+        }
+        QuestionType::Bool => {
+            question.default = answered
+                .get(&question.id)
+                .and_then(|value| value.as_bool())
+                .map(toml::Value::Boolean);
+        }
+        QuestionType::MultiSelect => {
+            for choice in question.choices.iter_mut().flatten() {
+                choice.default = answered
+                    .get(&choice.id)
+                    .and_then(|value| value.as_bool())
+                    .unwrap_or(false);
+            }
+        }
+        QuestionType::List => return None,
+    }
+    Some(question)
+}
 
-        if path_in_template.is_file() {
-            if path_in_template
-                .file_name()
-                .is_some_and(|name| name == "stamp.toml")
-            {
-                continue;
+/// Checks a given answer and stores it as if it had been asked for. Strings are accepted for every type of question,
+/// e.g. `true` for a `bool`, `a,b` for a `multi-select` and an inline toml array of tables for a `list`.
+fn apply_answer(
+    question: &Question,
+    answer: serde_json::Value,
+    context: &mut tera::Context,
+) -> eros::Result<()> {
+    use serde_json::Value;
+
+    let invalid = |expected: &str| {
+        format!(
+            "Answer `{}` to question `{}` is not valid, expected {}",
+            answer, question.id, expected
+        )
+    };
+    match question.kind {
+        QuestionType::String => {
+            let value = match &answer {
+                Value::String(value) => value.clone(),
+                Value::Number(_) | Value::Bool(_) => answer.to_string(),
+                _ => bail!("{}", invalid("a string")),
+            };
+            context.insert(&question.id, &value);
+        }
+        QuestionType::Bool => {
+            let value = match &answer {
+                Value::Bool(value) => *value,
+                Value::String(value) if value == "true" || value == "yes" => true,
+                Value::String(value) if value == "false" || value == "no" => false,
+                _ => bail!("{}", invalid("true or false")),
+            };
+            context.insert(&question.id, &value);
+        }
+        QuestionType::Select => {
+            let options = question.options.as_deref().unwrap_or_default();
+            let Some(value) = answer
+                .as_str()
+                .filter(|value| options.iter().any(|option| option == value))
+            else {
+                bail!("{}", invalid(&format!("one of {}", options.join(", "))))
+            };
+            context.insert(&question.id, value);
+        }
+        QuestionType::MultiSelect => {
+            let choices = question.choices.as_deref().unwrap_or_default();
+            let selected: Option<Vec<String>> = match &answer {
+                Value::Array(items) => items
+                    .iter()
+                    .map(|item| item.as_str().map(String::from))
+                    .collect(),
+                Value::String(value) => Some(
+                    value
+                        .split(',')
+                        .map(|id| id.trim().to_string())
+                        .filter(|id| !id.is_empty())
+                        .collect(),
+                ),
+                _ => None,
+            };
+            let ids: Vec<&str> = choices.iter().map(|c| c.id.as_str()).collect();
+            let Some(selected) =
+                selected.filter(|selected| selected.iter().all(|id| ids.contains(&id.as_str())))
+            else {
+                bail!("{}", invalid(&format!("a list of {}", ids.join(", "))))
+            };
+            for choice in choices {
+                context.insert(&choice.id, &selected.contains(&choice.id));
             }
+            context.insert(&question.id, &selected);
+        }
+        QuestionType::List => {
+            let items = match &answer {
+                Value::Array(items) => Some(items.clone()),
+                Value::String(value) => {
+                    toml::from_str::<toml::Table>(&format!("items = {}", value))
+                        .ok()
+                        .and_then(|table| serde_json::to_value(&table["items"]).ok())
+                        .and_then(|items| items.as_array().cloned())
+                }
+                _ => None,
+            };
+            let Some(items) = items.filter(|items| items.iter().all(Value::is_object)) else {
+                bail!("{}", invalid("an array of tables"))
+            };
+            let mut answered_items = Vec::new();
+            for (index, item) in items.into_iter().enumerate() {
+                let mut item_context = tera::Context::from_value(item)?;
+                for sub_question in question.questions.iter().flatten() {
+                    let prompt = format!("{} #{}: {}", question.id, index + 1, sub_question.prompt);
+                    answer_question(sub_question, &prompt, &mut item_context)?;
+                }
+                answered_items.push(item_context.into_json());
+            }
+            context.insert(&question.id, &answered_items);
+        }
+    }
+    Ok(())
+}
 
-            let relative_path_in_template = path_in_template.strip_prefix(&template_path)?;
-            let output_path_original = destination_path.join(relative_path_in_template);
-            let output_path: Result<PathBuf, String> = output_path_original
-                .components()
-                .map(|e| {
-                    let str_part = e.as_os_str().to_string_lossy();
-                    let processed_part = tera.render_str(&str_part, &context);
-                    processed_part.map_err(|_| str_part.to_string())
-                })
-                .try_fold(PathBuf::new(), |acc, part| Ok(acc.join(&part?)));
-            let output_path = output_path.map_err(|component_failed| {
-                let output_path = output_path_original.to_string_lossy();
-                eros::traced!(
-                    "Failed to render path component `{}` of `{}`",
-                    component_failed,
-                    output_path
-                )
-            })?;
+fn ask_question(
+    question: &Question,
+    prompt: &str,
+    context: &mut tera::Context,
+) -> eros::Result<()> {
+    let theme = ColorfulTheme::default();
 
-            let file_name = path_in_template
-                .file_name()
-                .unwrap_or_default()
-                .to_string_lossy();
-            let is_tera = file_name.ends_with(".tera") || file_name.contains(".tera.");
+    match question.kind {
+        QuestionType::String => {
+            let default_val = question
+                .default
+                .as_ref()
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
 
-            let mut final_output_path = output_path;
-            if is_tera {
-                let new_name = final_output_path
-                    .file_name()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .replace(".tera", "");
-                final_output_path.set_file_name(new_name);
+            let mut input = Input::<String>::with_theme(&theme);
+            input = input.with_prompt(prompt);
+
+            if let Some(default) = default_val {
+                input = input.default(default);
             }
 
-            actions.push(FileAction {
-                source: path_in_template.to_path_buf(),
-                destination: final_output_path,
-                is_tera,
-            });
+            let value = input.interact()?;
+            context.insert(&question.id, &value);
         }
-    }
+        QuestionType::Bool => {
+            let default_val = question
+                .default
+                .as_ref()
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+
+            let value = Confirm::with_theme(&theme)
+                .with_prompt(prompt)
+                .default(default_val)
+                .interact()?;
+            context.insert(&question.id, &value);
+        }
+        QuestionType::Select => {
+            if let Some(options) = &question.options {
+                let default_idx = question
+                    .default
+                    .as_ref()
+                    .and_then(|v| v.as_str())
+                    .and_then(|d| options.iter().position(|r| r == d))
+                    .unwrap_or(0);
 
-    match conflict_strategy {
-        ConflictStrategy::Fail => {
-            let mut conflicts = Vec::new();
-            for action in &actions {
-                if action.destination.exists() {
-                    conflicts.push(action.destination.clone());
+                let selection = Select::with_theme(&theme)
+                    .with_prompt(prompt)
+                    .default(default_idx)
+                    .items(options)
+                    .interact()?;
+
+                context.insert(&question.id, &options[selection]);
+            }
+        }
+        QuestionType::MultiSelect => {
+            if let Some(choices) = &question.choices {
+                let defaults: Vec<bool> = choices.iter().map(|c| c.default).collect();
+                let items: Vec<&String> = choices.iter().map(|c| &c.prompt).collect();
+
+                let selections = MultiSelect::with_theme(&theme)
+                    .with_prompt(prompt)
+                    .items(&items)
+                    .defaults(&defaults)
+                    .interact()?;
+
+                for (idx, choice) in choices.iter().enumerate() {
+                    let is_selected = selections.contains(&idx);
+                    context.insert(&choice.id, &is_selected);
                 }
+
+                let selected_ids: Vec<&String> =
+                    selections.iter().map(|&idx| &choices[idx].id).collect();
+                context.insert(&question.id, &selected_ids);
             }
-            if !conflicts.is_empty() {
-                eprintln!("Conflicting files found:");
-                for conflict in conflicts {
-                    eprintln!(" - {}", conflict.to_string_lossy());
+        }
+        QuestionType::List => {
+            let mut items = Vec::new();
+            loop {
+                let add = Confirm::with_theme(&theme)
+                    .with_prompt(format!(
+                        "{} Add {}?",
+                        prompt,
+                        if items.is_empty() {
+                            "an item"
+                        } else {
+                            "another"
+                        }
+                    ))
+                    .default(items.is_empty())
+                    .interact()?;
+                if !add {
+                    break;
+                }
+                let mut item_context = tera::Context::new();
+                for sub_question in question.questions.iter().flatten() {
+                    let prompt = format!(
+                        "  {} #{}: {}",
+                        question.id,
+                        items.len() + 1,
+                        sub_question.prompt
+                    );
+                    ask_question(sub_question, &prompt, &mut item_context)?;
                 }
-                bail!("Destination files already exist. Use --overwrite-conflicts or --skip-conflicts to resolve.");
+                items.push(item_context.into_json());
             }
+            context.insert(&question.id, &items);
         }
-        ConflictStrategy::Skip => {
-            actions.retain(|action| !action.destination.exists());
+    }
+    Ok(())
+}
+
+/// Ids of the answers referenced by the interpolations in `path`
+fn path_dependencies(path: &Path, questions: &[Question], delimiters: &Delimiters) -> Vec<String> {
+    text_dependencies(&path.to_string_lossy(), questions, delimiters)
+}
+
+/// Ids of the answers referenced by the tags in `text`
+fn text_dependencies(text: &str, questions: &[Question], delimiters: &Delimiters) -> Vec<String> {
+    let identifiers = delimiters.identifiers(text);
+    questions
+        .iter()
+        .flat_map(|q| {
+            std::iter::once(&q.id).chain(q.choices.iter().flatten().map(|choice| &choice.id))
+        })
+        .filter(|id| identifiers.contains(id.as_str()))
+        .cloned()
+        .collect()
+}
+
+/// Renders the interpolations in the path of `entry` and strips its `.tera` suffix. The result is guaranteed to be
+/// inside `destination_path`.
+fn output_path(
+    entry: &TemplateEntry,
+    destination_path: &Path,
+    renderer: &mut Renderer,
+    context: &tera::Context,
+) -> eros::Result<PathBuf> {
+    let mut output_path = destination_path.to_path_buf();
+    let component_count = entry.relative.components().count();
+    for (index, component) in entry.relative.components().enumerate() {
+        if entry.verbatim_components[index] {
+            output_path.push(component);
+            continue;
         }
-        ConflictStrategy::Overwrite => {
-            // Do nothing
+
+        let str_part = component.as_os_str().to_string_lossy();
+        let mut processed_part = renderer.render_str(&str_part, context).map_err(|_| {
+            eros::traced!(
+                "Failed to render path component `{}` of `{}`",
+                str_part,
+                entry.relative.to_string_lossy()
+            )
+        })?;
+        // Names written by the template author are used as is, only names built from answers are checked
+        let interpolated = processed_part != str_part;
+        if entry.has_tera_suffix() && index + 1 == component_count {
+            processed_part = processed_part.replace(".tera", "");
+        }
+
+        if interpolated && let Err(reason) = validate_path_component(&processed_part) {
+            let identifiers = renderer.delimiters.identifiers(&str_part);
+            let responsible: Vec<&str> = entry
+                .dependencies
+                .iter()
+                .filter(|id| identifiers.contains(id.as_str()))
+                .map(|id| id.as_str())
+                .collect();
+            if responsible.is_empty() {
+                bail!(
+                    "Path component `{}` of `{}` rendered to `{}`, which {}",
+                    str_part,
+                    entry.relative.to_string_lossy(),
+                    processed_part,
+                    reason
+                );
+            }
+            bail!(
+                "Path component `{}` of `{}` rendered to `{}`, which {}. Check the answer to question `{}`",
+                str_part,
+                entry.relative.to_string_lossy(),
+                processed_part,
+                reason,
+                responsible.join("`, `")
+            );
         }
+        output_path.push(processed_part);
+    }
+
+    if !normalize_path(&output_path).starts_with(normalize_path(destination_path)) {
+        bail!(
+            "`{}` would be written outside of the destination",
+            output_path.to_string_lossy()
+        );
     }
+    Ok(output_path)
+}
 
-    for action in actions {
-        if let Some(parent) = action.destination.parent() {
-            fs::create_dir_all(parent)?;
+/// Returns why `component` can not be used as a single file or directory name
+fn validate_path_component(component: &str) -> Result<(), &'static str> {
+    const RESERVED_NAMES: &[&str] = &[
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+
+    if component.trim().is_empty() {
+        return Err("is empty");
+    }
+    if component.contains(['/', '\\']) {
+        return Err("contains a path separator");
+    }
+    if component == "." || component == ".." {
+        return Err("is a relative path");
+    }
+    if component.contains('\0') {
+        return Err("contains a null character");
+    }
+    let stem = component.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        return Err("is a reserved file name");
+    }
+    Ok(())
+}
+
+/// Lexically resolves `.` and `..` components
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) {
+                    normalized.pop();
+                } else {
+                    normalized.push("..");
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Checks the entries whose dependencies have all been answered for conflicts and removes them from `unchecked`.
+/// Existing files are only conflicts when their content differs from the rendered content.
+fn check_early_conflicts(
+    unchecked: &mut Vec<&TemplateEntry>,
+    answered: &HashSet<&str>,
+    destination_path: &Path,
+    renderer: &mut Renderer,
+    context: &tera::Context,
+    replaceable: &HashSet<PathBuf>,
+) -> Result<(), Failure> {
+    let (ready, waiting): (Vec<&TemplateEntry>, Vec<&TemplateEntry>) =
+        unchecked.drain(..).partition(|entry| {
+            entry
+                .dependencies
+                .iter()
+                .all(|dependency| answered.contains(dependency.as_str()))
+        });
+    *unchecked = waiting;
+
+    let mut conflicts = Vec::new();
+    let mut staging: Option<StagingDir> = None;
+    for (index, entry) in ready.into_iter().enumerate() {
+        let output_path =
+            output_path(entry, destination_path, renderer, context).map_err(Failure::template)?;
+        if !is_conflict(entry, &output_path) || replaceable.contains(&output_path) {
+            continue;
         }
 
-        if action.is_tera {
-            let tera_template = fs::read_to_string(&action.source)?;
-            let rendered = tera.render_str(&tera_template, &context)?;
-            fs::write(action.destination, rendered)?;
-        } else {
-            fs::copy(&action.source, &action.destination)?;
+        let staging = match &mut staging {
+            Some(staging) => staging,
+            None => staging.insert(StagingDir::create()?),
+        };
+        let staged_path = staging.path.join(index.to_string());
+        // Entries that fail to render are reported once every answer is known
+        if stage_entry(
+            entry,
+            &staged_path,
+            &output_path,
+            destination_path,
+            renderer,
+            context,
+        )
+        .is_err()
+        {
+            continue;
+        }
+        if !is_unchanged(entry, &staged_path, &output_path) {
+            conflicts.push(output_path);
         }
     }
+    report_conflicts(conflicts).map_err(Failure::conflicts)
+}
 
-    println!("Template rendered successfully to {:?}", destination_path);
+/// Whether the staged output of `entry` is the same as what is already at `output_path`, including permissions
+fn is_unchanged(entry: &TemplateEntry, staged_path: &Path, output_path: &Path) -> bool {
+    match entry.kind {
+        EntryKind::File { .. } => {
+            let (Ok(staged), Ok(existing)) =
+                (fs::metadata(staged_path), fs::symlink_metadata(output_path))
+            else {
+                return false;
+            };
+            existing.is_file()
+                && staged.len() == existing.len()
+                && staged.permissions() == existing.permissions()
+                && fs::read(staged_path).ok() == fs::read(output_path).ok()
+        }
+        EntryKind::Symlink { .. } => {
+            output_path.is_symlink()
+                && fs::read_link(staged_path).ok() == fs::read_link(output_path).ok()
+        }
+        EntryKind::EmptyDir => output_path.is_dir(),
+    }
+}
+
+/// Whether writing `entry` to `output_path` would replace something. Existing directories are reused.
+fn is_conflict(entry: &TemplateEntry, output_path: &Path) -> bool {
+    match entry.kind {
+        EntryKind::EmptyDir => !output_path.is_dir() && output_path.exists(),
+        EntryKind::File { .. } | EntryKind::Symlink { .. } => {
+            output_path.symlink_metadata().is_ok()
+        }
+    }
+}
+
+fn report_conflicts(conflicts: Vec<PathBuf>) -> eros::Result<()> {
+    if !conflicts.is_empty() {
+        eprintln!("Conflicting files found:");
+        for conflict in conflicts {
+            eprintln!(" - {}", conflict.to_string_lossy());
+        }
+        bail!("Destination files already exist. Use --overwrite-conflicts or --skip-conflicts to resolve.");
+    }
     Ok(())
 }
 
-fn register_source(path: PathBuf) -> eros::Result<()> {
-    let mut registry = load_registry()?;
-    let canon_path = fs::canonicalize(&path)
-        .with_context(|| format!("Could not find path `{}`", path.to_string_lossy()))?;
+fn entry_kind_name(kind: &EntryKind) -> &'static str {
+    match kind {
+        EntryKind::File { is_tera: true, .. } => "rendered",
+        EntryKind::File { .. } => "copied",
+        EntryKind::Symlink { .. } => "symlink",
+        EntryKind::EmptyDir => "directory",
+    }
+}
+
+/// Prints the files of a render as a tree per status. Unchanged files are only counted unless verbose.
+fn print_render_summary(files: &[SummaryFile], verbosity: Verbosity) {
+    let groups = [
+        (FileStatus::Created, "Created"),
+        (FileStatus::Overwritten, "Overwritten"),
+        (FileStatus::Skipped, "Skipped"),
+        (FileStatus::Unchanged, "Unchanged"),
+        (FileStatus::Removed, "Removed"),
+    ];
+    for (status, heading) in groups {
+        let mut group: Vec<&SummaryFile> =
+            files.iter().filter(|file| file.status == status).collect();
+        if group.is_empty() {
+            continue;
+        }
+        group.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let paint = |text: String| match status {
+            FileStatus::Created => style(text).green(),
+            FileStatus::Overwritten => style(text).yellow(),
+            FileStatus::Skipped => style(text).cyan(),
+            FileStatus::Unchanged => style(text).dim(),
+            FileStatus::Removed => style(text).red(),
+        };
+        println!("{}", paint(format!("{} ({})", heading, group.len())).bold());
+        if status == FileStatus::Unchanged && verbosity != Verbosity::Verbose {
+            continue;
+        }
 
-    if !canon_path.is_dir() {
-        bail!("Path must be a directory");
+        let mut printed_dirs: HashSet<PathBuf> = HashSet::new();
+        for file in group {
+            let components: Vec<_> = file.path.components().collect();
+            let mut prefix = PathBuf::new();
+            for (depth, component) in components.iter().enumerate() {
+                prefix.push(component);
+                let is_last = depth + 1 == components.len();
+                let name = component.as_os_str().to_string_lossy();
+                if !is_last {
+                    if printed_dirs.insert(prefix.clone()) {
+                        println!("  {}{}/", "  ".repeat(depth), name);
+                    }
+                    continue;
+                }
+
+                let mut notes = vec![file.entry_kind.to_string()];
+                if verbosity == Verbosity::Verbose
+                    && let Some(source) = &file.source
+                {
+                    notes.push(format!("from {}", source.to_string_lossy()));
+                }
+                println!(
+                    "  {}{}  {}",
+                    "  ".repeat(depth),
+                    paint(name.to_string()),
+                    style(format!("({})", notes.join(", "))).dim()
+                );
+            }
+        }
     }
+}
 
-    if registry.sources.contains(&canon_path) {
-        println!(
-            "Source `{}` already registered",
-            canon_path.to_string_lossy()
-        );
-    } else {
-        registry.sources.push(canon_path.clone());
-        save_registry(&registry)?;
-        println!(
-            "Source `{}` registered successfully",
-            canon_path.to_string_lossy()
-        );
+/// Removes the files and empty directories a previous render of the session wrote that the template no longer
+/// produces, returning them along with their kind. Directories that are no longer empty are kept.
+fn remove_stale_outputs(
+    written: &HashSet<PathBuf>,
+    produced: &HashSet<PathBuf>,
+) -> eros::Result<Vec<(PathBuf, &'static str)>> {
+    let mut removed = Vec::new();
+    for path in written.difference(produced) {
+        let Ok(metadata) = path.symlink_metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            if fs::read_dir(path)?.next().is_some() {
+                continue;
+            }
+            fs::remove_dir(path)?;
+            removed.push((path.clone(), "directory"));
+        } else {
+            fs::remove_file(path)
+                .with_context(|| format!("Could not remove `{}`", path.to_string_lossy()))?;
+            removed.push((path.clone(), "file"));
+        }
     }
+    Ok(removed)
+}
 
+fn report_render_errors(render_errors: Vec<(PathBuf, String)>) -> eros::Result<()> {
+    if !render_errors.is_empty() {
+        eprintln!("Failed to render files:");
+        for (source, error) in render_errors {
+            eprintln!(" - {}: {}", source.to_string_lossy(), error);
+        }
+        bail!("Template rendering failed. The destination was left untouched.");
+    }
     Ok(())
 }
 
-fn list_templates() -> eros::Result<()> {
-    let registry = load_registry()?;
+/// Renders or copies `entry`, which is written to `output_path`, to `staged_path`
+fn stage_entry(
+    entry: &TemplateEntry,
+    staged_path: &Path,
+    output_path: &Path,
+    destination_path: &Path,
+    renderer: &mut Renderer,
+    context: &tera::Context,
+) -> Result<(), String> {
+    match &entry.kind {
+        EntryKind::File {
+            is_tera,
+            preserve_permissions,
+            ..
+        } => {
+            if *is_tera {
+                let tera_template = fs::read_to_string(&entry.source).map_err(|e| e.to_string())?;
+                let rendered = renderer
+                    .render_str(&tera_template, context)
+                    .map_err(|e| error_chain(&e))?;
+                fs::write(staged_path, rendered).map_err(|e| e.to_string())?;
+            } else {
+                // Copied files always keep their permissions
+                fs::copy(&entry.source, staged_path).map_err(|e| e.to_string())?;
+            }
+            if *is_tera && *preserve_permissions {
+                let permissions = fs::metadata(&entry.source)
+                    .map_err(|e| e.to_string())?
+                    .permissions();
+                fs::set_permissions(staged_path, permissions).map_err(|e| e.to_string())?;
+            }
+        }
+        EntryKind::Symlink {
+            target,
+            render_target,
+        } => {
+            let raw_target = target.to_string_lossy();
+            let target = if *render_target {
+                renderer
+                    .render_str(&raw_target, context)
+                    .map_err(|e| error_chain(&e))?
+            } else {
+                raw_target.to_string()
+            };
+            // Targets written by the template author are trusted, targets built from answers are not
+            if target != raw_target {
+                validate_symlink_target(Path::new(&target), output_path, destination_path)?;
+            }
+            let target_is_dir = entry
+                .source
+                .parent()
+                .is_some_and(|parent| parent.join(&target).is_dir());
+            create_symlink(Path::new(&target), staged_path, target_is_dir)
+                .map_err(|e| e.to_string())?;
+        }
+        EntryKind::EmptyDir => {}
+    }
+    Ok(())
+}
 
-    if registry.sources.is_empty() {
-        println!("No sources registered");
-        return Ok(());
+/// Checks that a rendered symlink target is made of valid names and points inside the destination
+fn validate_symlink_target(
+    target: &Path,
+    output_path: &Path,
+    destination_path: &Path,
+) -> Result<(), String> {
+    if target.has_root() || target.is_absolute() {
+        return Err(format!(
+            "Symlink target `{}` is an absolute path",
+            target.to_string_lossy()
+        ));
+    }
+    for component in target.components() {
+        if let Component::Normal(name) = component
+            && let Err(reason) = validate_path_component(&name.to_string_lossy())
+        {
+            return Err(format!(
+                "Symlink target `{}` has a component `{}`, which {}",
+                target.to_string_lossy(),
+                name.to_string_lossy(),
+                reason
+            ));
+        }
     }
+    let resolved = output_path
+        .parent()
+        .unwrap_or(destination_path)
+        .join(target);
+    if !normalize_path(&resolved).starts_with(normalize_path(destination_path)) {
+        return Err(format!(
+            "Symlink target `{}` points outside of the destination",
+            target.to_string_lossy()
+        ));
+    }
+    Ok(())
+}
 
-    let templates = find_templates(&registry.sources);
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path, _target_is_dir: bool) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
 
-    if templates.is_empty() {
-        println!("No templates found in registered sources");
-        return Ok(());
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path, target_is_dir: bool) -> std::io::Result<()> {
+    if target_is_dir {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
     }
+}
 
-    for template in templates {
-        print!("{}", style(&template.name).bold().cyan());
+impl FileRules {
+    fn new(config: &FilesConfig) -> eros::Result<Self> {
+        Ok(FileRules {
+            preserve_permissions: build_glob_set(Some(&config.preserve_permissions))?,
+            preserve_symlinks: build_glob_set(Some(&config.preserve_symlinks))?,
+            preserve_empty_dirs: build_glob_set(Some(&config.preserve_empty_dirs))?,
+            copy_without_render: build_glob_set(Some(&config.copy_without_render))?,
+            render: build_glob_set(Some(&config.render))?,
+            exclude: build_glob_set(Some(&config.exclude))?,
+            for_each: build_glob_set(Some(
+                &config
+                    .for_each
+                    .iter()
+                    .map(|rule| rule.path.clone())
+                    .collect::<Vec<_>>(),
+            ))?,
+        })
+    }
+}
 
-        if let Some(desc) = template.description {
-            print!(" - {}", style(desc).italic());
-        }
-        println!();
+/// Whether a file looks binary, e.g. an image or font, and so should never be rendered. Like git, this only looks at
+/// the start of the file.
+fn is_binary(path: &Path) -> eros::Result<bool> {
+    let mut buffer = [0; 8000];
+    let mut file = fs::File::open(path)?;
+    let read = file.read(&mut buffer)?;
+    let start = &buffer[..read];
+    if start.contains(&0) {
+        return Ok(true);
+    }
+    // An error without a length is a multi byte character cut off by the end of the buffer
+    Ok(std::str::from_utf8(start).is_err_and(|e| e.error_len().is_some()))
+}
 
-        println!("  {}", style(template.path.to_string_lossy()).dim());
-        println!();
+/// Builds a glob set where `*` does not cross directories. `None` matches everything.
+fn build_glob_set(patterns: Option<&[String]>) -> eros::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns.unwrap_or(&["**".to_string()]) {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("`{}` is not a valid glob", pattern))?;
+        builder.add(glob);
     }
+    Ok(builder.build()?)
+}
 
-    Ok(())
+/// A temporary directory that is removed when dropped
+struct StagingDir {
+    path: PathBuf,
 }
 
-fn load_registry() -> eros::Result<Registry> {
-    let registry_path = get_registry_path()?;
-    if let Ok(contents) = fs::read_to_string(&registry_path) {
-        let registry: Registry = serde_json::from_str(&contents).with_context(|| {
+impl StagingDir {
+    fn create() -> eros::Result<Self> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let path = std::env::temp_dir().join(format!("stamp-{}-{}", std::process::id(), timestamp));
+        fs::create_dir_all(&path).with_context(|| {
             format!(
-                "Registry from `{}` is not valid",
-                registry_path.to_string_lossy()
+                "Could not create staging directory `{}`",
+                path.to_string_lossy()
             )
         })?;
-        Ok(registry)
-    } else {
-        Ok(Registry::default())
+        Ok(StagingDir { path })
+    }
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
     }
 }
 
-fn get_registry_path() -> eros::Result<PathBuf> {
-    if let Some(proj_dirs) = ProjectDirs::from("com", "mcmah309", "stamp") {
-        let config_dir = proj_dirs.config_dir();
-        fs::create_dir_all(config_dir)?;
-        Ok(config_dir.join("template_registry.json"))
+/// Moves a file or symlink, falling back to copying when `from` and `to` are on different file systems
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    if fs::symlink_metadata(from)?.file_type().is_symlink() {
+        let target = fs::read_link(from)?;
+        if to.symlink_metadata().is_ok() {
+            fs::remove_file(to)?;
+        }
+        create_symlink(&target, to, to.is_dir())?;
     } else {
-        bail!("Could not determine configuration directory")
+        fs::copy(from, to)?;
     }
+    fs::remove_file(from)
 }
 
-fn save_registry(registry: &Registry) -> eros::Result<()> {
-    let registry_path = get_registry_path()?;
-    let contents = serde_json::to_string_pretty(registry)?;
-    fs::write(registry_path, contents)?;
-    Ok(())
+/// Formats an error with all of its sources, since e.g. tera keeps the useful details in the sources
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(": ");
+        message.push_str(&error.to_string());
+        source = error.source();
+    }
+    message
 }
 
-fn remove_source(path: PathBuf) -> eros::Result<()> {
-    let mut registry = load_registry()?;
-    let canon_path = if path.exists() {
-        fs::canonicalize(&path)?
-    } else {
-        path
-    };
+fn list_templates(
+    registry_override: Option<&Path>,
+    query: Option<&str>,
+    tags: &[String],
+    refresh: bool,
+    format: OutputFormat,
+) -> eros::Result<()> {
+    let registry = load_registry(registry_override)?;
+
+    if registry.sources.is_empty() && format == OutputFormat::Plain {
+        println!("No sources registered");
+        return Ok(());
+    }
 
-    if let Some(index) = registry.sources.iter().position(|r| *r == canon_path) {
-        registry.sources.remove(index);
-        save_registry(&registry)?;
+    let Discovered {
+        templates,
+        warnings,
+    } = find_templates(&registry.sources, refresh);
+    let filtered = query.is_some() || !tags.is_empty();
+    let templates: Vec<FoundTemplate> = templates
+        .into_iter()
+        .filter(|template| matches_filters(template, query, tags))
+        .collect();
+
+    if format != OutputFormat::Plain {
+        let templates = templates
+            .iter()
+            .map(|template| {
+                // Invalid configs are already reported in the warnings
+                let config = load_config(&template.path).ok();
+                let (meta, questions) = config
+                    .map(|config| (config.meta, config.questions))
+                    .unwrap_or_default();
+                TemplateSummary {
+                    name: template.name.clone(),
+                    qualified_name: template.qualified_name(),
+                    path: template.path.clone(),
+                    description: template.description.clone(),
+                    source: template.source.namespace(),
+                    tags: template.tags.clone(),
+                    authors: meta.authors,
+                    version: meta.version,
+                    homepage: meta.homepage,
+                    license: meta.license,
+                    questions,
+                }
+            })
+            .collect();
+        let listing = TemplateListing {
+            templates,
+            warnings,
+        };
+        return print_formatted(&listing, format);
+    }
+
+    for warning in &warnings {
+        eprintln!("{} {}", style("warning:").yellow().bold(), warning);
+    }
+    if !warnings.is_empty() {
+        eprintln!();
+    }
+
+    if templates.is_empty() {
+        if filtered {
+            println!("No templates match");
+        } else {
+            println!("No templates found in registered sources");
+        }
+        return Ok(());
+    }
+
+    for source in &registry.sources {
+        // Sources without matches are only noise when filtering
+        if filtered && !templates.iter().any(|t| t.source == *source) {
+            continue;
+        }
         println!(
-            "Source `{}` removed successfully",
-            canon_path.to_string_lossy()
-        );
-    } else {
-        bail!(
-            "Source `{}` not found in registry",
-            canon_path.to_string_lossy()
+            "{} {}",
+            style(source.namespace()).bold().underlined(),
+            style(source.path.to_string_lossy()).dim()
         );
+        println!();
+
+        let mut found_any = false;
+        for template in templates.iter().filter(|t| t.source == *source) {
+            found_any = true;
+            print!("  {}", style(&template.name).bold().cyan());
+
+            if let Some(desc) = &template.description {
+                print!(" - {}", style(desc).italic());
+            }
+            if !template.tags.is_empty() {
+                print!(
+                    " {}",
+                    style(format!("[{}]", template.tags.join(", "))).dim()
+                );
+            }
+            println!();
+
+            println!("    {}", style(template.path.to_string_lossy()).dim());
+            println!();
+        }
+        if !found_any {
+            println!("  {}", style("No templates found").dim());
+            println!();
+        }
     }
 
     Ok(())
 }
 
-struct FoundTemplate {
-    path: PathBuf,
-    name: String,
-    description: Option<String>,
-}
+fn show_template_info(template_name: &str, registry_override: Option<&Path>) -> eros::Result<()> {
+    let template = resolve_template(Some(template_name), registry_override)?;
+    let config = load_config(&template.path)?;
+
+    println!(
+        "{} {}",
+        style(&template.name).bold().cyan(),
+        style(template.qualified_name()).dim()
+    );
+    if let Some(description) = &config.meta.description {
+        println!("  {}", style(description).italic());
+    }
+    println!("  Path: {}", template.path.to_string_lossy());
+    if !config.meta.tags.is_empty() {
+        println!("  Tags: {}", config.meta.tags.join(", "));
+    }
+    if let Some(version) = &config.meta.version {
+        println!("  Version: {}", version);
+    }
+    if !config.meta.authors.is_empty() {
+        println!("  Authors: {}", config.meta.authors.join(", "));
+    }
+    if let Some(license) = &config.meta.license {
+        println!("  License: {}", license);
+    }
+    if let Some(homepage) = &config.meta.homepage {
+        println!("  Homepage: {}", homepage);
+    }
+    if let Some(min_stamp_version) = &config.meta.min_stamp_version {
+        println!("  Requires stamp: {} or newer", min_stamp_version);
+    }
+    if let Some(delimiters) = config.meta.delimiters.describe() {
+        println!("  Delimiters: {}", delimiters);
+    }
+    println!();
 
-fn find_templates(sources: &[PathBuf]) -> Vec<FoundTemplate> {
-    let mut templates = Vec::new();
-    // Used to prevent recursing into already found templates
-    let mut excluded_paths: HashSet<PathBuf> = HashSet::new();
-
-    for source in sources {
-        let walker = WalkBuilder::new(source)
-            .max_depth(Some(4))
-            .standard_filters(true)
-            .build();
-
-        for result in walker {
-            match result {
-                Ok(entry) => {
-                    if entry.file_type().is_some_and(|ft| ft.is_dir()) {
-                        let path = entry.path().to_path_buf();
-
-                        // Skip if ancestor is already a template
-                        if excluded_paths
-                            .iter()
-                            .any(|excluded| path.starts_with(excluded) && path != *excluded)
-                        {
-                            continue;
-                        }
+    println!("{}", style("Questions").bold().underlined());
+    if config.questions.is_empty() {
+        println!("  {}", style("None").dim());
+    }
+    for question in &config.questions {
+        print_question(question, 1);
+    }
+    println!();
 
-                        let config_path = path.join("stamp.toml");
-                        if config_path.exists() {
-                            // Found a template
-                            excluded_paths.insert(path.clone());
-
-                            let name = path
-                                .file_name()
-                                .unwrap_or_default()
-                                .to_string_lossy()
-                                .to_string();
-                            let mut description = None;
-                            let mut display_name = name.clone();
-
-                            if let Ok(contents) = fs::read_to_string(&config_path) {
-                                if let Ok(config) = toml::from_str::<TemplateConfig>(&contents) {
-                                    if let Some(n) = config.meta.name {
-                                        display_name = n;
-                                    }
-                                    description = config.meta.description;
-                                }
-                            }
-
-                            templates.push(FoundTemplate {
-                                path,
-                                name: display_name,
-                                description,
-                            });
-                        }
-                    }
+    println!("{}", style("Files").bold().underlined());
+    let entries = collect_entries(&template.path, &config, None)?;
+    if entries.is_empty() {
+        println!("  {}", style("None").dim());
+    }
+    let mut printed_dirs: HashSet<PathBuf> = HashSet::new();
+    for entry in &entries {
+        let components: Vec<_> = entry.relative.components().collect();
+        let mut prefix = PathBuf::new();
+        for (depth, component) in components.iter().enumerate() {
+            prefix.push(component);
+            let is_last = depth + 1 == components.len();
+            if !is_last && !printed_dirs.insert(prefix.clone()) {
+                continue;
+            }
+
+            let name = component.as_os_str().to_string_lossy();
+            let mut notes = Vec::new();
+            if !entry.verbatim_components[depth]
+                && !config.meta.delimiters.identifiers(&name).is_empty()
+            {
+                let dependencies = path_dependencies(
+                    Path::new(&*name),
+                    &config.questions,
+                    &config.meta.delimiters,
+                );
+                if dependencies.is_empty() {
+                    notes.push("interpolated".to_string());
+                } else {
+                    notes.push(format!("interpolated from {}", dependencies.join(", ")));
                 }
-                Err(err) => {
-                    eros::traced!("Error walking directory: {}", err);
+            }
+
+            let mut label = name.to_string();
+            if is_last && let Some(rule) = &entry.for_each {
+                notes.push(format!("one per item of {}", rule.items));
+            }
+            if is_last {
+                match &entry.kind {
+                    EntryKind::File { is_tera: true, .. } => notes.push("rendered".to_string()),
+                    EntryKind::File { .. } => {}
+                    EntryKind::Symlink { target, .. } => {
+                        label = format!("{} -> {}", label, target.to_string_lossy());
+                        notes.push("symlink".to_string());
+                    }
+                    EntryKind::EmptyDir => {
+                        label.push('/');
+                        notes.push("empty directory".to_string());
+                    }
                 }
+            } else {
+                label.push('/');
             }
+
+            print!("  {}{}", "  ".repeat(depth), label);
+            if !notes.is_empty() {
+                print!("  {}", style(format!("({})", notes.join(", "))).dim());
+            }
+            println!();
+        }
+    }
+
+    if let Some(readme) = find_readme(&template.path) {
+        let contents = fs::read_to_string(&readme)
+            .with_context(|| format!("could not read `{}`", readme.to_string_lossy()))?;
+        println!();
+        println!("{}", style("README").bold().underlined());
+        println!("{}", contents.trim_end());
+    }
+
+    Ok(())
+}
+
+/// Whether `template` has every tag in `tags` and, when given, contains `query` in its name, description or tags.
+/// Both are case insensitive.
+fn matches_filters(template: &FoundTemplate, query: Option<&str>, tags: &[String]) -> bool {
+    let has_tags = tags
+        .iter()
+        .all(|tag| template.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)));
+    let matches_query = query.is_none_or(|query| {
+        let query = query.to_lowercase();
+        template.name.to_lowercase().contains(&query)
+            || template
+                .description
+                .as_ref()
+                .is_some_and(|d| d.to_lowercase().contains(&query))
+            || template
+                .tags
+                .iter()
+                .any(|t| t.to_lowercase().contains(&query))
+    });
+    has_tags && matches_query
+}
+
+/// Prints a question for `stamp info`, followed by the sub-questions of lists
+fn print_question(question: &Question, depth: usize) {
+    let indent = "  ".repeat(depth);
+    println!(
+        "{}{} {} - {}",
+        indent,
+        style(&question.id).bold(),
+        style(format!("({})", question_type_name(&question.kind))).dim(),
+        question.prompt
+    );
+    if let Some(options) = &question.options {
+        println!("{}    options: {}", indent, options.join(", "));
+    }
+    if let Some(choices) = &question.choices {
+        for choice in choices {
+            let checkbox = if choice.default { "[x]" } else { "[ ]" };
+            println!(
+                "{}    {} {} - {}",
+                indent, checkbox, choice.id, choice.prompt
+            );
+        }
+    }
+    if let Some(default) = &question.default {
+        println!("{}    default: {}", indent, default);
+    }
+    for sub_question in question.questions.iter().flatten() {
+        print_question(sub_question, depth + 2);
+    }
+}
+
+/// The name of the question type as written in `stamp.toml`
+fn question_type_name(kind: &QuestionType) -> &'static str {
+    match kind {
+        QuestionType::String => "string",
+        QuestionType::Select => "select",
+        QuestionType::MultiSelect => "multi-select",
+        QuestionType::Bool => "bool",
+        QuestionType::List => "list",
+    }
+}
+
+fn find_readme(template_path: &Path) -> Option<PathBuf> {
+    fs::read_dir(template_path)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .find(|path| {
+            path.file_name().is_some_and(|name| {
+                let name = name.to_string_lossy().to_lowercase();
+                name == "readme" || name == "readme.md"
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_entry(relative: &str) -> TemplateEntry {
+        let relative = PathBuf::from(relative);
+        TemplateEntry {
+            source: Path::new("template").join(&relative),
+            verbatim_components: vec![false; relative.components().count()],
+            relative,
+            dependencies: vec!["name".to_string()],
+            kind: EntryKind::File {
+                is_tera: false,
+                has_tera_suffix: false,
+                preserve_permissions: false,
+            },
+            for_each: None,
         }
     }
 
-    templates
+    fn render_path(relative: &str, name: &str) -> eros::Result<PathBuf> {
+        let mut context = tera::Context::new();
+        context.insert("name", name);
+        let mut renderer = Renderer::new(Delimiters::default());
+        output_path(
+            &file_entry(relative),
+            Path::new("out"),
+            &mut renderer,
+            &context,
+        )
+    }
+
+    #[test]
+    fn normalize_path_resolves_relative_components() {
+        assert_eq!(normalize_path(Path::new("a/./b/../c")), Path::new("a/c"));
+        assert_eq!(normalize_path(Path::new("a/../../b")), Path::new("../b"));
+        assert_eq!(normalize_path(Path::new("/a/../b")), Path::new("/b"));
+    }
+
+    #[test]
+    fn validate_path_component_rejects_unusable_names() {
+        assert!(validate_path_component("main.rs").is_ok());
+        assert!(validate_path_component("console.rs").is_ok());
+        assert!(validate_path_component(" ").is_err());
+        assert!(validate_path_component("a/b").is_err());
+        assert!(validate_path_component("a\\b").is_err());
+        assert!(validate_path_component("..").is_err());
+        assert!(validate_path_component("a\0").is_err());
+        assert!(validate_path_component("con").is_err());
+        assert!(validate_path_component("LPT1.txt").is_err());
+    }
+
+    #[test]
+    fn output_path_only_validates_interpolated_components() {
+        assert_eq!(
+            render_path("src/aux.rs", "web").unwrap(),
+            Path::new("out/src/aux.rs")
+        );
+        assert_eq!(
+            render_path("src/{{ name }}.rs", "web").unwrap(),
+            Path::new("out/src/web.rs")
+        );
+        assert!(render_path("src/{{ name }}.rs", "aux").is_err());
+        assert!(render_path("{{ name }}/x", "..").is_err());
+        assert!(render_path("{{ name }}/x", "a/../..").is_err());
+    }
 }
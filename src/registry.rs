@@ -0,0 +1,414 @@
+use crate::build_glob_set;
+use directories::ProjectDirs;
+use eros::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Environment variable pointing to an additional registry file
+const REGISTRY_ENV_VAR: &str = "STAMP_REGISTRY";
+/// Registry file discovered in the current directory or its ancestors, meant to be checked in and shared by a team
+const PROJECT_REGISTRY_FILE: &str = "stamp.registry.toml";
+
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct Registry {
+    pub sources: Vec<Source>,
+}
+
+/// A registered directory containing templates
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(from = "SourceRepr")]
+pub struct Source {
+    /// Used to address templates as `<name>/<template>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub options: SourceOptions,
+}
+
+/// How a source is searched for templates
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct SourceOptions {
+    /// How many directories deep templates are searched for. Defaults to 4.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+    /// When not empty, only directories matching one of these globs are considered templates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Directories matching one of these globs are not searched
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Whether directories ignored by `.gitignore` files are searched. Defaults to false.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search_gitignored: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SourceRepr {
+    /// Registries used to store only the path of each source
+    Path(PathBuf),
+    Named {
+        name: Option<String>,
+        path: PathBuf,
+        #[serde(flatten)]
+        options: SourceOptions,
+    },
+}
+
+impl From<SourceRepr> for Source {
+    fn from(repr: SourceRepr) -> Self {
+        match repr {
+            SourceRepr::Path(path) => Source {
+                name: None,
+                path,
+                options: SourceOptions::default(),
+            },
+            SourceRepr::Named {
+                name,
+                path,
+                options,
+            } => Source {
+                name,
+                path,
+                options,
+            },
+        }
+    }
+}
+
+impl Source {
+    /// The name templates from this source are namespaced under. Defaults to the directory name.
+    pub fn namespace(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => self
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+}
+
+/// Registers a source, or updates the name and options of an already registered one. `options` is `None` when none
+/// were given.
+pub fn register_source(
+    path: PathBuf,
+    name: Option<String>,
+    options: Option<SourceOptions>,
+    registry_override: Option<&Path>,
+) -> eros::Result<()> {
+    let registry_path = writable_registry_path(registry_override)?;
+    // Registering into a `--registry` file creates it
+    let mut registry = read_registry_file(&registry_path, true)?;
+    let base = registry_base(&registry_path);
+    let canon_path = fs::canonicalize(&path)
+        .with_context(|| format!("Could not find path `{}`", path.to_string_lossy()))?;
+
+    if !canon_path.is_dir() {
+        bail!("Path must be a directory");
+    }
+
+    if let Some(options) = &options {
+        for pattern in options.include.iter().chain(&options.exclude) {
+            build_glob_set(Some(std::slice::from_ref(pattern)))?;
+        }
+    }
+
+    if let Some(name) = &name
+        && (name.is_empty() || name.contains(['/', '\\']))
+    {
+        bail!(
+            "Source name `{}` must be non-empty and cannot contain `/`",
+            name
+        );
+    }
+
+    // Templates are addressed as `<source>/<template>`, so every source needs its own namespace
+    let existing = registry
+        .sources
+        .iter()
+        .find(|s| resolve(&base, &s.path) == canon_path);
+    let namespace = match (&name, existing) {
+        (Some(name), _) => name.clone(),
+        (None, Some(source)) => source.namespace(),
+        (None, None) => Source {
+            name: None,
+            path: canon_path.clone(),
+            options: SourceOptions::default(),
+        }
+        .namespace(),
+    };
+    let others: Vec<Source> = registry
+        .sources
+        .iter()
+        .map(|s| Source {
+            path: resolve(&base, &s.path),
+            ..s.clone()
+        })
+        // The `--registry` file may not exist yet, and its sources are already included
+        .chain(load_registry(None)?.sources)
+        .filter(|s| s.path != canon_path)
+        .collect();
+    if let Some(other) = others.iter().find(|s| s.namespace() == namespace) {
+        bail!(
+            "Source name `{}` is already used by `{}`. Choose another with --name.",
+            namespace,
+            other.path.to_string_lossy()
+        );
+    }
+
+    if let Some(source) = registry
+        .sources
+        .iter_mut()
+        .find(|s| resolve(&base, &s.path) == canon_path)
+    {
+        let renamed = name.is_some() && source.name != name;
+        let reconfigured = options.as_ref().is_some_and(|o| *o != source.options);
+        if !renamed && !reconfigured {
+            println!(
+                "Source `{}` already registered",
+                canon_path.to_string_lossy()
+            );
+            return Ok(());
+        }
+        if renamed {
+            source.name = name;
+        }
+        if let Some(options) = options {
+            source.options = options;
+        }
+        let label = source.namespace();
+        save_registry(&registry, &registry_path)?;
+        println!(
+            "Source `{}` updated as `{}`",
+            canon_path.to_string_lossy(),
+            label
+        );
+    } else {
+        // Sources within the directory of a shared registry are stored relative to it, so the registry can be
+        // checked in
+        let stored_path = match (is_json(&registry_path), canon_path.strip_prefix(&base)) {
+            (false, Ok(relative)) if !relative.as_os_str().is_empty() => relative.to_path_buf(),
+            _ => canon_path.clone(),
+        };
+        let source = Source {
+            name,
+            path: stored_path,
+            options: options.unwrap_or_default(),
+        };
+        let label = source.namespace();
+        registry.sources.push(source);
+        save_registry(&registry, &registry_path)?;
+        println!(
+            "Source `{}` registered successfully as `{}`",
+            canon_path.to_string_lossy(),
+            label
+        );
+    }
+
+    Ok(())
+}
+
+/// Removes a source by name or path
+pub fn remove_source(source: String, registry_override: Option<&Path>) -> eros::Result<()> {
+    let registry_path = writable_registry_path(registry_override)?;
+    let mut registry = read_registry_file(&registry_path, registry_override.is_none())?;
+    let base = registry_base(&registry_path);
+
+    let index = match registry
+        .sources
+        .iter()
+        .position(|s| s.name.as_ref() == Some(&source))
+    {
+        Some(index) => Some(index),
+        None => {
+            let path = PathBuf::from(&source);
+            let canon_path = if path.exists() {
+                fs::canonicalize(&path)?
+            } else {
+                path
+            };
+            registry
+                .sources
+                .iter()
+                .position(|s| resolve(&base, &s.path) == canon_path)
+        }
+    };
+
+    if let Some(index) = index {
+        let removed = registry.sources.remove(index);
+        save_registry(&registry, &registry_path)?;
+        println!(
+            "Source `{}` removed successfully",
+            resolve(&base, &removed.path).to_string_lossy()
+        );
+    } else {
+        bail!(
+            "Source `{}` not found in registry `{}`",
+            source,
+            registry_path.to_string_lossy()
+        );
+    }
+
+    Ok(())
+}
+
+/// Loads the sources of every registry layer. In order of precedence these are the `--registry` file, the
+/// `STAMP_REGISTRY` file, the closest `stamp.registry.toml` in the current directory or its ancestors, and the user
+/// registry. A source is left out if a layer with higher precedence already has its path or name.
+pub fn load_registry(registry_override: Option<&Path>) -> eros::Result<Registry> {
+    let mut merged = Registry::default();
+    let user_registry_path = get_registry_path()?;
+    for registry_path in registry_layers(registry_override)? {
+        // Only the user registry may not exist yet. Registries given explicitly must exist.
+        let registry = read_registry_file(&registry_path, registry_path == user_registry_path)?;
+        let base = registry_base(&registry_path);
+        for mut source in registry.sources {
+            source.path = resolve(&base, &source.path);
+            let shadowed = merged
+                .sources
+                .iter()
+                .any(|s| s.path == source.path || (source.name.is_some() && s.name == source.name));
+            if !shadowed {
+                merged.sources.push(source);
+            }
+        }
+    }
+    Ok(merged)
+}
+
+fn registry_layers(registry_override: Option<&Path>) -> eros::Result<Vec<PathBuf>> {
+    let mut layers = Vec::new();
+    if let Some(path) = registry_override {
+        layers.push(path.to_path_buf());
+    }
+    if let Some(path) = env::var_os(REGISTRY_ENV_VAR).filter(|path| !path.is_empty()) {
+        layers.push(PathBuf::from(path));
+    }
+    let current_dir = env::current_dir()?;
+    if let Some(path) = current_dir
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_REGISTRY_FILE))
+        .find(|path| path.is_file())
+    {
+        layers.push(path);
+    }
+    layers.push(get_registry_path()?);
+    Ok(layers)
+}
+
+/// The registry `register` and `remove` modify
+fn writable_registry_path(registry_override: Option<&Path>) -> eros::Result<PathBuf> {
+    match registry_override {
+        Some(path) => Ok(path.to_path_buf()),
+        None => get_registry_path(),
+    }
+}
+
+/// Reads a registry file as written, without resolving relative paths. A missing file is an empty registry when
+/// `allow_missing`, and an error otherwise.
+fn read_registry_file(registry_path: &Path, allow_missing: bool) -> eros::Result<Registry> {
+    let contents = match fs::read_to_string(registry_path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            if allow_missing {
+                return Ok(Registry::default());
+            }
+            bail!(
+                "Registry `{}` does not exist",
+                registry_path.to_string_lossy()
+            );
+        }
+        Err(error) => {
+            return Err(error).with_context(|| {
+                format!(
+                    "Could not read registry `{}`",
+                    registry_path.to_string_lossy()
+                )
+            });
+        }
+    };
+    let registry: Registry = if is_json(registry_path) {
+        serde_json::from_str(&contents).with_context(|| {
+            format!(
+                "Registry from `{}` is not valid",
+                registry_path.to_string_lossy()
+            )
+        })?
+    } else {
+        toml::from_str(&contents).with_context(|| {
+            format!(
+                "Registry from `{}` is not valid",
+                registry_path.to_string_lossy()
+            )
+        })?
+    };
+    if is_json(registry_path) && is_legacy_format(&contents) {
+        save_registry(&registry, registry_path)?;
+    }
+    Ok(registry)
+}
+
+/// Whether the registry still stores sources as plain paths
+fn is_legacy_format(contents: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(contents).is_ok_and(|value| {
+        value["sources"]
+            .as_array()
+            .is_some_and(|sources| sources.iter().any(serde_json::Value::is_string))
+    })
+}
+
+/// The user registry is json, while registries meant to be shared are toml
+fn is_json(registry_path: &Path) -> bool {
+    registry_path.extension().is_some_and(|ext| ext == "json")
+}
+
+/// The directory relative source paths in a registry are resolved against
+fn registry_base(registry_path: &Path) -> PathBuf {
+    let parent = registry_path.parent().unwrap_or(Path::new("."));
+    let parent = if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    };
+    fs::canonicalize(parent).unwrap_or_else(|_| parent.to_path_buf())
+}
+
+fn resolve(base: &Path, path: &Path) -> PathBuf {
+    let path = base.join(path);
+    fs::canonicalize(&path).unwrap_or(path)
+}
+
+fn get_registry_path() -> eros::Result<PathBuf> {
+    Ok(config_dir()?.join("template_registry.json"))
+}
+
+/// The user configuration directory, holding the user registry and config. Created if it does not exist.
+pub fn config_dir() -> eros::Result<PathBuf> {
+    if let Some(proj_dirs) = ProjectDirs::from("com", "mcmah309", "stamp") {
+        let config_dir = proj_dirs.config_dir();
+        fs::create_dir_all(config_dir)?;
+        Ok(config_dir.to_path_buf())
+    } else {
+        bail!("Could not determine configuration directory")
+    }
+}
+
+fn save_registry(registry: &Registry, registry_path: &Path) -> eros::Result<()> {
+    let contents = if is_json(registry_path) {
+        serde_json::to_string_pretty(registry)?
+    } else {
+        toml::to_string_pretty(registry)?
+    };
+    if let Some(parent) = registry_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(registry_path, contents)?;
+    Ok(())
+}
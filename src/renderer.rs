@@ -0,0 +1,330 @@
+use serde::Deserialize;
+use std::{borrow::Cow, collections::HashSet};
+use tera::Tera;
+
+const TERA_VARIABLE: [&str; 2] = ["{{", "}}"];
+const TERA_BLOCK: [&str; 2] = ["{%", "%}"];
+const TERA_COMMENT: [&str; 2] = ["{#", "#}"];
+
+/// Alternative start and end delimiters for the tera syntax, e.g. `["[[", "]]"]` for variables. Useful when the
+/// rendered files use `{{ }}` themselves.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct Delimiters {
+    #[serde(default)]
+    variable: Option<[String; 2]>,
+    #[serde(default)]
+    block: Option<[String; 2]>,
+    #[serde(default)]
+    comment: Option<[String; 2]>,
+}
+
+fn custom_or<'a>(delimiters: &'a Option<[String; 2]>, default: [&'static str; 2]) -> [&'a str; 2] {
+    delimiters
+        .as_ref()
+        .map(|[start, end]| [start.as_str(), end.as_str()])
+        .unwrap_or(default)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TagKind {
+    Variable,
+    Block,
+    Comment,
+}
+
+enum Segment<'a> {
+    Literal(&'a str),
+    Tag { kind: TagKind, inner: &'a str },
+}
+
+impl Delimiters {
+    fn is_default(&self) -> bool {
+        self.variable.is_none() && self.block.is_none() && self.comment.is_none()
+    }
+
+    /// The delimiters in use for each kind of tag, along with the tera delimiters they are translated to
+    fn pairs(&self) -> [(TagKind, [&str; 2], [&'static str; 2]); 3] {
+        [
+            (
+                TagKind::Variable,
+                custom_or(&self.variable, TERA_VARIABLE),
+                TERA_VARIABLE,
+            ),
+            (
+                TagKind::Block,
+                custom_or(&self.block, TERA_BLOCK),
+                TERA_BLOCK,
+            ),
+            (
+                TagKind::Comment,
+                custom_or(&self.comment, TERA_COMMENT),
+                TERA_COMMENT,
+            ),
+        ]
+    }
+
+    /// Problems with the configured delimiters
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let pairs = self.pairs();
+        for (i, (_, [start, end], _)) in pairs.iter().enumerate() {
+            if start.is_empty() || end.is_empty() {
+                errors.push("Delimiters cannot be empty".to_string());
+            }
+            if pairs[..i].iter().any(|(_, [other, _], _)| other == start) {
+                errors.push(format!(
+                    "Delimiter `{}` is used for more than one tag",
+                    start
+                ));
+            }
+        }
+        errors
+    }
+
+    fn segments<'a>(&self, text: &'a str) -> Vec<Segment<'a>> {
+        let pairs = self.pairs();
+        let mut segments = Vec::new();
+        let mut literal_start = 0;
+        let mut position = 0;
+        while position < text.len() {
+            let rest = &text[position..];
+            let opened = pairs
+                .iter()
+                .filter(|(_, [start, _], _)| !start.is_empty() && rest.starts_with(start))
+                .max_by_key(|(_, [start, _], _)| start.len());
+            let Some((kind, [start, end], _)) = opened else {
+                position += rest.chars().next().map_or(1, char::len_utf8);
+                continue;
+            };
+            let inner_start = position + start.len();
+            let Some(inner_len) = text[inner_start..].find(end) else {
+                break;
+            };
+            if literal_start < position {
+                segments.push(Segment::Literal(&text[literal_start..position]));
+            }
+            segments.push(Segment::Tag {
+                kind: *kind,
+                inner: &text[inner_start..inner_start + inner_len],
+            });
+            position = inner_start + inner_len + end.len();
+            literal_start = position;
+        }
+        if literal_start < text.len() {
+            segments.push(Segment::Literal(&text[literal_start..]));
+        }
+        segments
+    }
+
+    /// Rewrites `text` to use the tera delimiters. Literal text that tera could mistake for a tag is escaped. The
+    /// contents of raw blocks are left as written.
+    fn to_tera<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.is_default() {
+            return Cow::Borrowed(text);
+        }
+        let pairs = self.pairs();
+        let mut translated = String::with_capacity(text.len());
+        let mut in_raw = false;
+        for segment in self.segments(text) {
+            match segment {
+                Segment::Tag {
+                    kind: TagKind::Block,
+                    inner,
+                } if in_raw && is_block(inner, "endraw") => {
+                    in_raw = false;
+                    translated.push_str(TERA_BLOCK[0]);
+                    translated.push_str(inner);
+                    translated.push_str(TERA_BLOCK[1]);
+                }
+                Segment::Literal(literal) if in_raw => translated.push_str(literal),
+                Segment::Tag { kind, inner } if in_raw => {
+                    let (_, [start, end], _) = pairs
+                        .iter()
+                        .find(|(pair_kind, _, _)| *pair_kind == kind)
+                        .expect("every tag kind has delimiters");
+                    translated.push_str(start);
+                    translated.push_str(inner);
+                    translated.push_str(end);
+                }
+                Segment::Literal(literal) if literal.contains('{') => {
+                    translated.push_str("{% raw %}");
+                    translated.push_str(literal);
+                    translated.push_str("{% endraw %}");
+                }
+                Segment::Literal(literal) => translated.push_str(literal),
+                Segment::Tag { kind, inner } => {
+                    in_raw = kind == TagKind::Block && is_block(inner, "raw");
+                    let (_, _, [start, end]) = pairs
+                        .iter()
+                        .find(|(pair_kind, _, _)| *pair_kind == kind)
+                        .expect("every tag kind has delimiters");
+                    translated.push_str(start);
+                    translated.push_str(inner);
+                    translated.push_str(end);
+                }
+            }
+        }
+        Cow::Owned(translated)
+    }
+
+    /// A description of the delimiters that differ from tera's, e.g. ``variable `[[ ]]` ``. `None` when all are the
+    /// defaults.
+    pub fn describe(&self) -> Option<String> {
+        if self.is_default() {
+            return None;
+        }
+        let described: Vec<String> = [
+            ("variable", &self.variable),
+            ("block", &self.block),
+            ("comment", &self.comment),
+        ]
+        .into_iter()
+        .filter_map(|(kind, delimiters)| {
+            delimiters
+                .as_ref()
+                .map(|[start, end]| format!("{} `{} {}`", kind, start, end))
+        })
+        .collect();
+        Some(described.join(", "))
+    }
+
+    /// All identifiers used within the variable and block tags of `text`. Attributes, e.g. `name` in `service.name`,
+    /// are left out since they are not variables.
+    pub fn identifiers(&self, text: &str) -> HashSet<String> {
+        let mut identifiers = HashSet::new();
+        for segment in self.segments(text) {
+            if let Segment::Tag {
+                kind: TagKind::Variable | TagKind::Block,
+                inner,
+            } = segment
+            {
+                identifiers.extend(
+                    inner
+                        .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                        .filter_map(|path| path.split('.').next())
+                        .filter(|s| !s.is_empty())
+                        .map(String::from),
+                );
+            }
+        }
+        identifiers
+    }
+}
+
+/// Whether the inner text of a block tag is the `name` keyword, allowing for whitespace control, e.g. `- raw -`
+fn is_block(inner: &str, name: &str) -> bool {
+    inner.trim().trim_matches('-').trim() == name
+}
+
+/// Renders text with tera, using the template's delimiters
+pub struct Renderer {
+    tera: Tera,
+    pub delimiters: Delimiters,
+}
+
+impl Renderer {
+    pub fn new(delimiters: Delimiters) -> Self {
+        let mut tera = Tera::default();
+        tera.autoescape_on(vec![]);
+        tera.set_escape_fn(|e| e.to_string());
+        Renderer { tera, delimiters }
+    }
+
+    pub fn render_str(&mut self, text: &str, context: &tera::Context) -> tera::Result<String> {
+        let text = self.delimiters.to_tera(text);
+        self.tera.render_str(&text, context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom() -> Delimiters {
+        Delimiters {
+            variable: Some(["[[".to_string(), "]]".to_string()]),
+            block: Some(["[%".to_string(), "%]".to_string()]),
+            comment: None,
+        }
+    }
+
+    fn render(delimiters: Delimiters, text: &str) -> String {
+        let mut context = tera::Context::new();
+        context.insert("name", "web");
+        context.insert("public", &true);
+        Renderer::new(delimiters)
+            .render_str(text, &context)
+            .unwrap()
+    }
+
+    #[test]
+    fn default_delimiters_are_left_as_is() {
+        let text = "{{ name }} {% if public %}yes{% endif %}";
+        assert!(matches!(
+            Delimiters::default().to_tera(text),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(render(Delimiters::default(), text), "web yes");
+    }
+
+    #[test]
+    fn custom_delimiters_are_translated() {
+        assert_eq!(
+            custom().to_tera("[[ name ]] [% if public %]yes[% endif %]"),
+            "{{ name }} {% if public %}yes{% endif %}"
+        );
+        assert_eq!(
+            render(custom(), "[[ name ]] [% if public %]yes[% endif %]"),
+            "web yes"
+        );
+    }
+
+    #[test]
+    fn literal_tera_syntax_is_escaped() {
+        assert_eq!(
+            custom().to_tera("{{ .Values }} [[ name ]]"),
+            "{% raw %}{{ .Values }} {% endraw %}{{ name }}"
+        );
+        assert_eq!(
+            render(custom(), "{{ .Values }} [[ name ]]"),
+            "{{ .Values }} web"
+        );
+        // Tags that are not customized keep the tera delimiters
+        assert_eq!(render(custom(), "{# x #}[[ name ]]"), "web");
+        let delimiters = Delimiters {
+            comment: Some(["[#".to_string(), "#]".to_string()]),
+            ..custom()
+        };
+        assert_eq!(render(delimiters, "{# x #}[# y #][[ name ]]"), "{# x #}web");
+    }
+
+    #[test]
+    fn raw_blocks_are_output_as_written() {
+        assert_eq!(
+            render(
+                custom(),
+                "[% raw %][[ name ]] {{ name }} [% if %][% endraw %] [[ name ]]"
+            ),
+            "[[ name ]] {{ name }} [% if %] web"
+        );
+        assert_eq!(
+            render(custom(), "[%- raw -%] [[ name ]] [%- endraw -%]"),
+            "[[ name ]]"
+        );
+    }
+
+    #[test]
+    fn unclosed_start_delimiter_is_literal() {
+        assert_eq!(custom().to_tera("a [[ name"), "a [[ name");
+        assert_eq!(render(custom(), "[[ name ]] [[ b"), "web [[ b");
+    }
+
+    #[test]
+    fn identifiers_leave_out_attributes() {
+        let identifiers = custom().identifiers("[[ svc.name ]] [% if public %] {{ other }}");
+        assert!(identifiers.contains("svc"));
+        assert!(identifiers.contains("public"));
+        assert!(!identifiers.contains("name"));
+        assert!(!identifiers.contains("other"));
+    }
+}
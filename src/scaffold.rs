@@ -0,0 +1,571 @@
+use crate::{create_symlink, is_binary, registry, MultiChoice, Question, QuestionType};
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
+use eros::{bail, Context};
+use ignore::WalkBuilder;
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The `stamp.toml` written for a new template
+#[derive(Serialize)]
+struct NewConfig {
+    meta: NewMeta,
+    questions: Vec<Question>,
+}
+
+#[derive(Serialize)]
+struct NewMeta {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+const QUESTION_TYPES: [(&str, QuestionType); 5] = [
+    ("string", QuestionType::String),
+    ("select", QuestionType::Select),
+    ("multi-select", QuestionType::MultiSelect),
+    ("bool", QuestionType::Bool),
+    ("list", QuestionType::List),
+];
+
+/// Interactively creates a template skeleton in `directory`
+pub fn new_template(directory: PathBuf, registry_override: Option<&Path>) -> eros::Result<()> {
+    if directory.join("stamp.toml").exists() {
+        bail!(
+            "`{}` is already a template",
+            directory.join("stamp.toml").to_string_lossy()
+        );
+    }
+    if directory.is_dir() && fs::read_dir(&directory)?.next().is_some() {
+        bail!(
+            "`{}` is not empty. Use `stamp extract` to turn an existing project into a template.",
+            directory.to_string_lossy()
+        );
+    }
+
+    let theme = ColorfulTheme::default();
+    let default_name = fs::canonicalize(&directory)
+        .unwrap_or_else(|_| directory.clone())
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name: String = Input::with_theme(&theme)
+        .with_prompt("Template name")
+        .default(default_name)
+        .interact_text()?;
+    let description: String = Input::with_theme(&theme)
+        .with_prompt("Description")
+        .allow_empty(true)
+        .interact_text()?;
+
+    let mut questions: Vec<Question> = Vec::new();
+    while Confirm::with_theme(&theme)
+        .with_prompt("Add a question?")
+        .default(questions.is_empty())
+        .interact()?
+    {
+        let question = ask_new_question(&theme, &questions)?;
+        questions.push(question);
+    }
+
+    let config = NewConfig {
+        meta: NewMeta {
+            name,
+            description: (!description.is_empty()).then_some(description),
+        },
+        questions,
+    };
+
+    fs::create_dir_all(&directory)
+        .with_context(|| format!("Could not create `{}`", directory.to_string_lossy()))?;
+    fs::write(
+        directory.join("stamp.toml"),
+        toml::to_string_pretty(&config)?,
+    )?;
+    fs::write(
+        directory.join("README.md.tera"),
+        example_file(&config.meta.name, &config.questions),
+    )?;
+    println!("Template created in {:?}", directory);
+
+    offer_registration(&theme, &directory, registry_override)
+}
+
+fn ask_new_question(theme: &ColorfulTheme, existing: &[Question]) -> eros::Result<Question> {
+    let type_index = Select::with_theme(theme)
+        .with_prompt("Question type")
+        .items(QUESTION_TYPES.map(|(name, _)| name))
+        .default(0)
+        .interact()?;
+    let (_, kind) = QUESTION_TYPES[type_index];
+
+    let taken: Vec<&str> = existing
+        .iter()
+        .flat_map(|q| {
+            std::iter::once(q.id.as_str()).chain(q.choices.iter().flatten().map(|c| c.id.as_str()))
+        })
+        .collect();
+    let id = ask_identifier(
+        theme,
+        "Question id, used in templates as `{{ id }}`",
+        &taken,
+    )?;
+    let prompt: String = Input::with_theme(theme)
+        .with_prompt("Prompt")
+        .interact_text()?;
+
+    let mut question = Question {
+        id,
+        kind,
+        prompt,
+        default: None,
+        options: None,
+        choices: None,
+        questions: None,
+    };
+    match kind {
+        QuestionType::String => {
+            let default: String = Input::with_theme(theme)
+                .with_prompt("Default answer (leave empty for none)")
+                .allow_empty(true)
+                .interact_text()?;
+            question.default = (!default.is_empty()).then_some(toml::Value::String(default));
+        }
+        QuestionType::Bool => {
+            let default = Confirm::with_theme(theme)
+                .with_prompt("Default answer")
+                .default(false)
+                .interact()?;
+            question.default = Some(toml::Value::Boolean(default));
+        }
+        QuestionType::Select => {
+            let options = loop {
+                let options: String = Input::with_theme(theme)
+                    .with_prompt("Options, separated by commas")
+                    .interact_text()?;
+                let options: Vec<String> = options
+                    .split(',')
+                    .map(|option| option.trim().to_string())
+                    .filter(|option| !option.is_empty())
+                    .collect();
+                if !options.is_empty() {
+                    break options;
+                }
+            };
+            let default = Select::with_theme(theme)
+                .with_prompt("Default option")
+                .items(&options)
+                .default(0)
+                .interact()?;
+            question.default = Some(toml::Value::String(options[default].clone()));
+            question.options = Some(options);
+        }
+        QuestionType::MultiSelect => {
+            let mut taken = taken;
+            taken.push(&question.id);
+            let mut choices: Vec<MultiChoice> = Vec::new();
+            loop {
+                let taken: Vec<&str> = taken
+                    .iter()
+                    .copied()
+                    .chain(choices.iter().map(|c| c.id.as_str()))
+                    .collect();
+                let id =
+                    ask_identifier(theme, "Choice id, true in templates when selected", &taken)?;
+                let prompt: String = Input::with_theme(theme)
+                    .with_prompt("Choice prompt")
+                    .interact_text()?;
+                let default = Confirm::with_theme(theme)
+                    .with_prompt("Selected by default?")
+                    .default(false)
+                    .interact()?;
+                choices.push(MultiChoice {
+                    id,
+                    prompt,
+                    default,
+                });
+                if !Confirm::with_theme(theme)
+                    .with_prompt("Add another choice?")
+                    .default(true)
+                    .interact()?
+                {
+                    break;
+                }
+            }
+            question.choices = Some(choices);
+        }
+        QuestionType::List => {
+            // Sub-questions are fields of each item, so their ids only need to be unique within the list
+            let mut sub_questions: Vec<Question> = Vec::new();
+            loop {
+                println!("Question asked for each item of `{}`:", question.id);
+                sub_questions.push(ask_new_question(theme, &sub_questions)?);
+                if !Confirm::with_theme(theme)
+                    .with_prompt("Add another question for each item?")
+                    .default(false)
+                    .interact()?
+                {
+                    break;
+                }
+            }
+            question.questions = Some(sub_questions);
+        }
+    }
+    Ok(question)
+}
+
+/// Asks for an id usable as a tera variable that is not in `taken`
+fn ask_identifier(theme: &ColorfulTheme, prompt: &str, taken: &[&str]) -> eros::Result<String> {
+    let taken: Vec<String> = taken.iter().map(|id| id.to_string()).collect();
+    let id = Input::<String>::with_theme(theme)
+        .with_prompt(prompt)
+        .validate_with(move |id: &String| -> Result<(), String> {
+            if !is_identifier(id) {
+                Err(
+                    "Ids may only contain letters, digits and `_`, and cannot start with a digit"
+                        .to_string(),
+                )
+            } else if taken.contains(id) {
+                Err(format!("`{}` is already used", id))
+            } else {
+                Ok(())
+            }
+        })
+        .interact_text()?;
+    Ok(id)
+}
+
+/// Whether `id` can be used as a tera variable
+fn is_identifier(id: &str) -> bool {
+    let mut chars = id.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A file using every answer, showing how each kind of question is used in a template
+fn example_file(name: &str, questions: &[Question]) -> String {
+    let mut contents = format!("# {}\n\nRendered from the `{}` template.\n", name, name);
+    for question in questions {
+        contents.push('\n');
+        match question.kind {
+            QuestionType::String | QuestionType::Select => {
+                contents.push_str(&format!("{}: {{{{ {} }}}}\n", question.prompt, question.id));
+            }
+            QuestionType::Bool => {
+                contents.push_str(&format!(
+                    "{{% if {} %}}{}: yes{{% else %}}{}: no{{% endif %}}\n",
+                    question.id, question.prompt, question.prompt
+                ));
+            }
+            QuestionType::MultiSelect => {
+                contents.push_str(&format!("{}\n", question.prompt));
+                for choice in question.choices.iter().flatten() {
+                    contents.push_str(&format!(
+                        "{{% if {} %}}- {}\n{{% endif %}}",
+                        choice.id, choice.prompt
+                    ));
+                }
+            }
+            QuestionType::List => {
+                contents.push_str(&format!(
+                    "{}\n{{% for item in {} %}}-",
+                    question.prompt, question.id
+                ));
+                for sub_question in question.questions.iter().flatten() {
+                    let value = match sub_question.kind {
+                        QuestionType::MultiSelect => {
+                            format!("item.{} | join(sep=\", \")", sub_question.id)
+                        }
+                        QuestionType::List => format!("item.{} | length", sub_question.id),
+                        _ => format!("item.{}", sub_question.id),
+                    };
+                    contents.push_str(&format!(" {}: {{{{ {} }}}}", sub_question.prompt, value));
+                }
+                contents.push_str("\n{% endfor %}");
+            }
+        }
+    }
+    contents
+}
+
+/// Offers to register the template's parent directory, unless a registered source already contains the template
+fn offer_registration(
+    theme: &ColorfulTheme,
+    directory: &Path,
+    registry_override: Option<&Path>,
+) -> eros::Result<()> {
+    let directory = fs::canonicalize(directory)?;
+    let Some(parent) = directory.parent() else {
+        return Ok(());
+    };
+    let registry = registry::load_registry(registry_override)?;
+    if registry
+        .sources
+        .iter()
+        .any(|source| directory.starts_with(&source.path))
+    {
+        return Ok(());
+    }
+
+    let register = Confirm::with_theme(theme)
+        .with_prompt(format!(
+            "Register `{}` as a template source?",
+            parent.to_string_lossy()
+        ))
+        .default(false)
+        .interact()?;
+    if register {
+        registry::register_source(parent.to_path_buf(), None, None, registry_override)?;
+    }
+    Ok(())
+}
+
+/// Parses a `--var <id>=<value>` argument
+pub fn parse_var(arg: &str) -> Result<(String, String), String> {
+    let Some((id, value)) = arg.split_once('=') else {
+        return Err(format!("`{}` should be of the form `<id>=<value>`", arg));
+    };
+    if !is_identifier(id) {
+        return Err(format!(
+            "`{}` may only contain letters, digits and `_`, and cannot start with a digit",
+            id
+        ));
+    }
+    if value.is_empty() {
+        return Err(format!("The value of `{}` cannot be empty", id));
+    }
+    Ok((id.to_string(), value.to_string()))
+}
+
+/// Copies `project` to `template_dir`, replacing each value in `vars` with an interpolation of its id, in both file
+/// contents and path names. Paths ignored by the project's `.gitignore` files are left out.
+pub fn extract_template(
+    project: PathBuf,
+    template_dir: PathBuf,
+    vars: Vec<(String, String)>,
+) -> eros::Result<()> {
+    let project = fs::canonicalize(&project)
+        .with_context(|| format!("Could not find project `{}`", project.to_string_lossy()))?;
+    if !project.is_dir() {
+        bail!(
+            "Project `{}` must be a directory",
+            project.to_string_lossy()
+        );
+    }
+    let mut ids = HashSet::new();
+    if let Some((id, _)) = vars.iter().find(|(id, _)| !ids.insert(id)) {
+        bail!("`--var {}` is given more than once", id);
+    }
+    if template_dir.is_dir() && fs::read_dir(&template_dir)?.next().is_some() {
+        bail!("`{}` is not empty", template_dir.to_string_lossy());
+    }
+    fs::create_dir_all(&template_dir)
+        .with_context(|| format!("Could not create `{}`", template_dir.to_string_lossy()))?;
+    let template_dir = fs::canonicalize(&template_dir)?;
+
+    let excluded = template_dir.clone();
+    let walker = WalkBuilder::new(&project)
+        .standard_filters(true)
+        .hidden(false)
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        // The template may be created within the project
+        .filter_entry(move |entry| entry.file_name() != ".git" && entry.path() != excluded)
+        .build();
+
+    let mut copied = 0;
+    let mut templated = 0;
+    for entry in walker {
+        let entry = entry?;
+        let Some(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(&project)?;
+        let mut output = template_dir.clone();
+        for component in relative.components() {
+            let component = component.as_os_str().to_string_lossy();
+            output.push(replace_values(&component, &vars, false).unwrap_or(component.to_string()));
+        }
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        if file_type.is_symlink() {
+            let target = fs::read_link(entry.path())?;
+            create_symlink(&target, &output, entry.path().is_dir())?;
+        } else if is_binary(entry.path())? {
+            fs::copy(entry.path(), &output)?;
+        } else {
+            // Files that are not valid UTF-8 are copied as is
+            let replaced = fs::read_to_string(entry.path())
+                .ok()
+                .and_then(|contents| replace_values(&contents, &vars, true));
+            match replaced {
+                Some(replaced) => {
+                    let mut name = output.file_name().unwrap_or_default().to_os_string();
+                    name.push(".tera");
+                    output.set_file_name(name);
+                    fs::write(&output, replaced)?;
+                    templated += 1;
+                }
+                None => {
+                    fs::copy(entry.path(), &output)?;
+                }
+            }
+        }
+        copied += 1;
+    }
+
+    let name = template_dir
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let config = NewConfig {
+        meta: NewMeta {
+            name,
+            description: None,
+        },
+        questions: vars
+            .into_iter()
+            .map(|(id, value)| Question {
+                prompt: id.clone(),
+                id,
+                kind: QuestionType::String,
+                default: Some(toml::Value::String(value)),
+                options: None,
+                choices: None,
+                questions: None,
+            })
+            .collect(),
+    };
+    fs::write(
+        template_dir.join("stamp.toml"),
+        toml::to_string_pretty(&config)?,
+    )?;
+
+    println!(
+        "Extracted {} files, {} of them rendered, into {:?}",
+        copied, templated, template_dir
+    );
+    Ok(())
+}
+
+/// Replaces every occurrence of the values in `vars` with an interpolation of their id, preferring the longest value
+/// when several match at the same position. `None` when nothing was replaced.
+fn replace_values(text: &str, vars: &[(String, String)], escape: bool) -> Option<String> {
+    let mut replaced = String::with_capacity(text.len());
+    let mut literal = String::new();
+    let mut changed = false;
+    let mut position = 0;
+    while position < text.len() {
+        let rest = &text[position..];
+        let found = vars
+            .iter()
+            .filter(|(_, value)| rest.starts_with(value.as_str()))
+            .max_by_key(|(_, value)| value.len());
+        match found {
+            Some((id, value)) => {
+                push_literal(&mut replaced, &literal, escape);
+                literal.clear();
+                replaced.push_str(&format!("{{{{ {} }}}}", id));
+                position += value.len();
+                changed = true;
+            }
+            None => {
+                let c = rest.chars().next().expect("position is within the text");
+                literal.push(c);
+                position += c.len_utf8();
+            }
+        }
+    }
+    push_literal(&mut replaced, &literal, escape);
+    changed.then_some(replaced)
+}
+
+/// Appends text that must render as is. With `escape`, text that tera would otherwise interpret, including a trailing
+/// `{` that would run into a following `{{`, is wrapped in a raw block.
+fn push_literal(output: &mut String, literal: &str, escape: bool) {
+    let is_tera_syntax = ["{{", "{%", "{#"]
+        .iter()
+        .any(|opening| literal.contains(opening))
+        || literal.ends_with('{');
+    if escape && is_tera_syntax {
+        output.push_str("{% raw %}");
+        output.push_str(literal);
+        output.push_str("{% endraw %}");
+    } else {
+        output.push_str(literal);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> Vec<(String, String)> {
+        vec![
+            ("name".to_string(), "my-app".to_string()),
+            ("full_name".to_string(), "my-app-server".to_string()),
+        ]
+    }
+
+    /// Renders `template` as a render would, for checking that it reproduces the original text
+    fn render(template: &str) -> String {
+        let mut context = tera::Context::new();
+        context.insert("name", "my-app");
+        context.insert("full_name", "my-app-server");
+        tera::Tera::one_off(template, &context, false).unwrap()
+    }
+
+    #[test]
+    fn values_are_replaced_by_tags() {
+        let replaced = replace_values("name = \"my-app\"", &vars(), true).unwrap();
+        assert_eq!(replaced, "name = \"{{ name }}\"");
+        assert_eq!(render(&replaced), "name = \"my-app\"");
+    }
+
+    #[test]
+    fn longest_value_wins() {
+        let replaced = replace_values("my-app-server my-app", &vars(), true).unwrap();
+        assert_eq!(replaced, "{{ full_name }} {{ name }}");
+    }
+
+    #[test]
+    fn text_without_values_is_unchanged() {
+        assert_eq!(replace_values("nothing here", &vars(), true), None);
+    }
+
+    #[test]
+    fn existing_tera_syntax_is_escaped() {
+        let text = "{{ .Values }} {% if %} {# c #} my-app";
+        let replaced = replace_values(text, &vars(), true).unwrap();
+        assert_eq!(
+            replaced,
+            "{% raw %}{{ .Values }} {% if %} {# c #} {% endraw %}{{ name }}"
+        );
+        assert_eq!(render(&replaced), text);
+    }
+
+    #[test]
+    fn brace_before_value_is_escaped() {
+        let replaced = replace_values("{my-app}", &vars(), true).unwrap();
+        assert_eq!(replaced, "{% raw %}{{% endraw %}{{ name }}}");
+        assert_eq!(render(&replaced), "{my-app}");
+    }
+
+    #[test]
+    fn path_components_are_not_escaped() {
+        let mut output = String::new();
+        push_literal(&mut output, "{{ x }}", false);
+        assert_eq!(output, "{{ x }}");
+    }
+}
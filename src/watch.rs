@@ -0,0 +1,82 @@
+use crate::{render_template, ConflictStrategy, OutputFormat, RenderSession, Verbosity};
+use console::style;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+/// How long to wait for more changes before rendering, since saving a file often produces several events
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Renders the template, then renders it again whenever it changes. Answers are remembered between renders, so only
+/// questions added since are asked. Failed renders are reported without exiting.
+pub fn watch_template(
+    template_path: PathBuf,
+    destination_path: PathBuf,
+    conflict_strategy: ConflictStrategy,
+    format: OutputFormat,
+    verbosity: Verbosity,
+    mut session: RenderSession,
+) -> eros::Result<()> {
+    let render = |session: &mut RenderSession| {
+        if let Err(error) = render_template(
+            template_path.clone(),
+            destination_path.clone(),
+            conflict_strategy,
+            format,
+            verbosity,
+            session,
+        ) {
+            eprintln!("{:?}", error.error);
+        }
+    };
+    render(&mut session);
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&template_path, RecursiveMode::Recursive)?;
+    println!(
+        "{}",
+        style(format!(
+            "Watching {:?} for changes. Press Ctrl-C to stop.",
+            template_path
+        ))
+        .dim()
+    );
+
+    while let Ok(event) = receiver.recv() {
+        let mut changed = is_relevant(event, &destination_path);
+        while let Ok(event) = receiver.recv_timeout(DEBOUNCE) {
+            changed |= is_relevant(event, &destination_path);
+        }
+        if changed {
+            println!();
+            println!("{}", style("Template changed, rendering again").bold());
+            render(&mut session);
+        }
+    }
+    Ok(())
+}
+
+/// Whether `event` changed the template. Changes within the destination, which may be inside the template, and
+/// within `.git` are ignored.
+fn is_relevant(event: notify::Result<Event>, destination_path: &Path) -> bool {
+    let Ok(event) = event else {
+        return false;
+    };
+    if matches!(event.kind, EventKind::Access(_)) {
+        return false;
+    }
+    let destination = fs::canonicalize(destination_path).ok();
+    event.paths.iter().any(|path| {
+        !destination
+            .as_ref()
+            .is_some_and(|destination| path.starts_with(destination))
+            && !path
+                .components()
+                .any(|component| component.as_os_str() == ".git")
+    })
+}